@@ -0,0 +1,68 @@
+use anyhow::Result;
+use multilint::{config::LinterConfig, linter::Linter};
+use std::{
+    fs::{self, File},
+    time::{Duration, Instant},
+};
+
+/// Timings of a synthetic run
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub files: usize,
+    pub batches: usize,
+
+    /// Time spent listing the files
+    pub walk: Duration,
+
+    /// Time spent in the whole run, including another walk
+    pub total: Duration,
+}
+
+/// Runs a no-op linter over a temporary tree of `files` files
+pub fn bench(files: usize) -> Result<BenchReport> {
+    let root = tempfile::tempdir()?;
+    // spread files over directories as in real trees
+    for i in 0..files {
+        let dir = root.path().join(format!("dir{}", i / 100));
+        if i % 100 == 0 {
+            fs::create_dir(&dir)?;
+        }
+        File::create(dir.join(format!("file{}.txt", i)))?;
+    }
+
+    let linter = Linter::from_config(
+        LinterConfig {
+            command: "true".to_string(),
+            includes: vec!["*.txt".to_string()],
+            ..Default::default()
+        },
+        &Default::default(),
+    );
+    let start = Instant::now();
+    linter.matched_paths(&root)?;
+    let walk = start.elapsed();
+
+    let start = Instant::now();
+    let output = linter.run(&root)?;
+    let total = start.elapsed();
+
+    Ok(BenchReport {
+        files: output.as_ref().map_or(0, |o| o.stats().files),
+        batches: output.as_ref().map_or(0, |o| o.stats().batches),
+        walk,
+        total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bench;
+    use test_log::test;
+
+    #[test]
+    fn synthetic() {
+        let report = bench(150).unwrap();
+        assert_eq!(report.files, 150);
+        assert!(report.batches >= 1);
+    }
+}
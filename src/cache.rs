@@ -0,0 +1,157 @@
+use crate::{config::hex, index::CACHE_DIR};
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const RESULTS_DIR: &str = "results";
+
+/// Result of a linter run stored with the hashes of its inputs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// SHA-256 of the contents of each input, by path
+    inputs: BTreeMap<String, String>,
+
+    pub exit_code: i32,
+
+    /// Exit code of the failing batch, which differs from `exit_code` if there were several
+    #[serde(default)]
+    pub failure_code: Option<i32>,
+
+    /// Signal that killed a batch
+    #[serde(default)]
+    pub signal: Option<i32>,
+
+    pub stdout: Vec<u8>,
+
+    pub stderr: Vec<u8>,
+}
+
+/// Stored result of a linter over a set of files under `.multilint-cache`
+///
+/// The record is looked up by `key`, which identifies the settings of the linter, and is only
+/// replayed while the inputs have the same paths and contents as when it was stored.
+pub struct ResultCache {
+    path: PathBuf,
+    inputs: BTreeMap<String, String>,
+}
+
+impl ResultCache {
+    /// Hashes the files of a linter identified by `key` under the root
+    pub fn new(root: impl AsRef<Path>, key: &str, files: &[PathBuf]) -> Result<Self> {
+        let mut inputs = BTreeMap::new();
+        for file in files {
+            let contents = fs::read(root.as_ref().join(file))
+                .with_context(|| format!("Cannot read \"{}\"", file.display()))?;
+            inputs.insert(
+                file.to_string_lossy().into_owned(),
+                hex(&Sha256::digest(contents)),
+            );
+        }
+        let path = root
+            .as_ref()
+            .join(CACHE_DIR)
+            .join(RESULTS_DIR)
+            .join(format!("{}.json", key));
+        Ok(Self { path, inputs })
+    }
+
+    /// Returns the stored result if the inputs have not changed since it was stored
+    pub fn load(&self) -> Option<Record> {
+        let data = fs::read(&self.path).ok()?;
+        let record: Record = match serde_json::from_slice(&data) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("ignoring cache {}: {}", self.path.display(), e);
+                return None;
+            }
+        };
+        if record.inputs != self.inputs {
+            debug!("cache {}: inputs changed", self.path.display());
+            return None;
+        }
+        Some(record)
+    }
+
+    /// Stores the result for the current inputs, replacing any previous one
+    pub fn store(
+        &self,
+        exit_code: i32,
+        failure_code: Option<i32>,
+        signal: Option<i32>,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<()> {
+        let record = Record {
+            inputs: self.inputs.clone(),
+            exit_code,
+            failure_code,
+            signal,
+            stdout: stdout.to_vec(),
+            stderr: stderr.to_vec(),
+        };
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&self.path, serde_json::to_vec(&record)?))
+            .with_context(|| format!("Cannot write cache \"{}\"", self.path.display()))
+    }
+}
+
+/// Derives a file name from the parts identifying the settings of a linter
+pub fn key<I, S>(parts: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<[u8]>,
+{
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_ref());
+        hasher.update([0]);
+    }
+    hex(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key, ResultCache};
+    use std::{fs, path::PathBuf};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn replay() {
+        let root = tempdir().unwrap();
+        fs::write(root.path().join("a.rs"), "fn a() {}").unwrap();
+        let files = vec![PathBuf::from("a.rs")];
+        let cache = |key: &str, files: &[PathBuf]| ResultCache::new(&root, key, files).unwrap();
+
+        assert!(cache("k", &files).load().is_none());
+        cache("k", &files)
+            .store(123, Some(1), None, b"out", b"err")
+            .unwrap();
+        let record = cache("k", &files).load().unwrap();
+        assert_eq!(record.exit_code, 123);
+        assert_eq!(record.failure_code, Some(1));
+        assert_eq!(record.signal, None);
+        assert_eq!(record.stdout, b"out");
+        assert_eq!(record.stderr, b"err");
+
+        // other settings, files or contents miss
+        assert!(cache("other", &files).load().is_none());
+        assert!(cache("k", &[]).load().is_none());
+        fs::write(root.path().join("a.rs"), "fn b() {}").unwrap();
+        assert!(cache("k", &files).load().is_none());
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(key(["a", "b"]), key(["a", "b"]));
+        assert_ne!(key(["a", "b"]), key(["ab"]));
+        assert_eq!(key::<_, &str>([]).len(), 64);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{config, linter::Linter, parser::Parser};
+use serde::Serialize;
+use std::path::Path;
+
+/// A problem found in the config
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    /// Linter with the problem, or `None` for the whole config
+    pub linter: Option<String>,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(linter: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            linter: linter.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+/// Loads the merged config and validates it without running linters
+pub fn check_config(config_path: impl AsRef<Path>, config_file: Option<&Path>) -> Vec<Problem> {
+    let config = match config::from_file_or_path(config_file, &config_path) {
+        Ok(config) => config,
+        Err(e) => return vec![Problem::new(None, format!("{:#}", e))],
+    };
+
+    let mut problems: Vec<Problem> = config
+        .unknown_fields
+        .iter()
+        .map(|field| Problem::new(None, format!("Unknown field \"{}\"", field)))
+        .collect();
+    for (name, linter_config) in &config.linter {
+        let name = Some(name.as_str());
+        let linter = Linter::from_config(linter_config.clone(), &config.global);
+        if let Err(e) = linter.check_globs() {
+            problems.push(Problem::new(name, format!("{:#}", e)));
+        }
+        if let Err(e) = linter.check_output_filter() {
+            problems.push(Problem::new(name, format!("{:#}", e)));
+        }
+        if let Err(e) = linter.check_kill_signal() {
+            problems.push(Problem::new(name, format!("{:#}", e)));
+        }
+        let formats = [
+            &linter_config.error_format,
+            &linter_config.error_format_stdout,
+            &linter_config.error_format_stderr,
+            &linter_config.note_format,
+        ];
+        for e in formats.into_iter().filter_map(|f| Parser::new(f).err()) {
+            problems.push(Problem::new(name, format!("{:#}", e)));
+        }
+        for (field, pointer) in linter_config.json_fields.pointers() {
+            if !pointer.is_empty() && !pointer.starts_with('/') {
+                problems.push(Problem::new(
+                    name,
+                    format!(
+                        "json_fields.{} \"{}\" does not start with /",
+                        field, pointer
+                    ),
+                ));
+            }
+        }
+        let work_dir = &linter_config.work_dir;
+        if !work_dir.as_os_str().is_empty() && !work_dir.is_dir() && !linter_config.create_work_dir
+        {
+            problems.push(Problem::new(
+                name,
+                format!("work_dir \"{}\" is not a directory", work_dir.display()),
+            ));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_config;
+    use std::{fs::File, io::Write};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn problems() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("multilint.toml");
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.good]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+        assert!(check_config(root.path(), None).is_empty());
+
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.bad]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "includs = ['*']").unwrap();
+            writeln!(config, "excludes = ['a{{b']").unwrap();
+            writeln!(config, "error_format = ['%x']").unwrap();
+            writeln!(config, "work_dir = 'missing'").unwrap();
+            writeln!(config, "json_fields = {{ line = 'location/row' }}").unwrap();
+        }
+        let problems = check_config(root.path(), None);
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(problems.len(), 5, "{:?}", messages);
+        assert!(messages.contains(&"json_fields.line \"location/row\" does not start with /"));
+        assert_eq!(messages[0], "Unknown field \"linter.bad.includs\"");
+        assert!(problems[0].linter.is_none());
+        assert!(problems[1..]
+            .iter()
+            .all(|p| p.linter.as_deref() == Some("bad")));
+
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.bad]").unwrap();
+        }
+        assert_eq!(check_config(root.path(), None).len(), 1);
+    }
+}
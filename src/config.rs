@@ -1,5 +1,9 @@
-use anyhow::{Context, Result};
+use crate::error::{MultilintError, Result};
+use anyhow::{bail, ensure, Context};
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use log::debug;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::{
     collections::BTreeMap,
     fs::read_to_string,
@@ -12,6 +16,10 @@ fn bool_true() -> bool {
 
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct Root {
+    /// Stop searching parent directories for config files
+    #[serde(default)]
+    pub root: bool,
+
     /// Settings applied to all linters
     #[serde(default)]
     pub global: GlobalConfig,
@@ -19,6 +27,21 @@ pub struct Root {
     /// Linter settings
     #[serde(default)]
     pub linter: BTreeMap<String, LinterConfig>,
+
+    /// SHA-256 of the merged config tables
+    ///
+    /// Comments, layout and key order do not change it, but equivalent settings do, such as
+    /// setting a value to its default.
+    #[serde(skip)]
+    pub digest: String,
+
+    /// Keys in the config files that are not settings, such as `linter.x.typo`
+    #[serde(skip)]
+    pub unknown_fields: Vec<String>,
+
+    /// Config files merged into this one, from the root, then the pattern files they name
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -26,17 +49,78 @@ pub struct GlobalConfig {
     /// Glob patterns to exclude files
     #[serde(default)]
     pub excludes: Vec<String>,
+
+    /// Files or directories marking the project root, where searching parent directories for config files stops
+    #[serde(default)]
+    pub root_markers: Vec<String>,
+
+    /// Skip files larger than this size in bytes
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+
+    /// Number of linters run at once (default: 1)
+    #[serde(default)]
+    pub jobs: Option<usize>,
+
+    /// Maximum number of linters with each tag running at once
+    #[serde(default)]
+    pub group_limits: BTreeMap<String, usize>,
+
+    /// Cache the file tree in `.multilint-cache` to speed up repeated runs
+    #[serde(default)]
+    pub index: bool,
+
+    /// Cache the results of linters in `.multilint-cache` and replay them while their files and settings are unchanged
+    #[serde(default)]
+    pub cache: bool,
+
+    /// Linters that config files in subdirectories may define (any if not set)
+    #[serde(default)]
+    pub allow_linters: Option<Vec<String>>,
+
+    /// Glob patterns of config fragments, relative to the config file, merged after the config files in sorted order
+    #[serde(default)]
+    pub include_configs: Vec<String>,
+
+    /// Skip files ignored by `.gitignore`, `.git/info/exclude` and the global gitignore of git (default value in toml is `true`)
+    #[serde(default = "bool_true")]
+    pub respect_gitignore: bool,
+
+    /// Names of additional ignore files, such as `.lintignore`, applied like `.gitignore`
+    #[serde(default)]
+    pub ignore_files: Vec<String>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct LinterConfig {
     /// Linter command to run
+    #[serde(default)]
     pub command: String,
 
-    /// Arguments
+    /// Command line run by `sh -c` (`cmd /C` on Windows) with the files appended, instead of `command` and `options`
+    #[serde(default)]
+    pub shell: Option<String>,
+
+    /// Group of interchangeable linters, of which only the first with an executable command runs
+    #[serde(default)]
+    pub alternative_group: Option<String>,
+
+    /// Labels grouping linters, such as for `global.group_limits`
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Linters with a higher priority run and are reported first, so that `--fail-fast` stops at them (default: 0)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Arguments, where `{root}` is replaced with the absolute path of the root
     #[serde(default)]
     pub options: Vec<String>,
 
+    /// Environment variables of the linter, where `${VAR}` expands to the variable of multilint
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
     /// Glob patterns for files to be processed by the linter
     #[serde(default)]
     pub includes: Vec<String>,
@@ -45,10 +129,14 @@ pub struct LinterConfig {
     #[serde(default)]
     pub excludes: Vec<String>,
 
-    /// Working directory for the linter
+    /// Working directory for the linter, where relative paths in `options` are resolved
     #[serde(default)]
     pub work_dir: PathBuf,
 
+    /// Create `work_dir` with its parents if it does not exist, such as a generated build directory
+    #[serde(default)]
+    pub create_work_dir: bool,
+
     /// Exclude git submodules (default value in toml is `true`)
     #[serde(default = "bool_true")]
     pub exclude_submodules: bool,
@@ -57,42 +145,659 @@ pub struct LinterConfig {
     #[serde(default)]
     pub single_file: bool,
 
+    /// Number of batches of files run at once (default 1), still bounded by `--max-procs`
+    pub max_procs: Option<usize>,
+
+    /// Process only files that git reports as untracked or modified
+    #[serde(default)]
+    pub uncommitted_only: bool,
+
+    /// Pass the files in a temporary response file as a single `@<path>` argument, one per line (ignored with `single_file`)
+    #[serde(default)]
+    pub argfile: bool,
+
+    /// Run on copies of the files in a temporary directory, reporting modifications without applying them
+    #[serde(default)]
+    pub sandbox: bool,
+
+    /// Run the linter in a pseudo-terminal, so that tools without a `--color=always` option color their output (unix only)
+    #[serde(default)]
+    pub pty: bool,
+
+    /// Run the linter again while it modifies files, such as formatters needing several passes
+    #[serde(default)]
+    pub retry_until_stable: bool,
+
+    /// Maximum number of runs with `retry_until_stable` (default: 5)
+    #[serde(default)]
+    pub max_passes: Option<usize>,
+
+    /// Run the linter again up to this many times while it fails, such as tools fetching from the network
+    #[serde(default)]
+    pub retries: u32,
+
+    /// Exit codes that make `retries` run the linter again (default: any failure but a timeout)
+    #[serde(default)]
+    pub retry_on_exit_codes: Vec<i32>,
+
+    /// Kill each run of the linter after this many seconds, failing with exit code 124
+    #[serde(default)]
+    pub timeout: Option<u64>,
+
+    /// Signal sent to a run exceeding `timeout`, such as `TERM` to let it clean up, before killing it after `kill_grace` (default: `KILL`, unix only)
+    #[serde(default)]
+    pub kill_signal: Option<String>,
+
+    /// Seconds between `kill_signal` and killing the run (default: 5)
+    #[serde(default)]
+    pub kill_grace: Option<u64>,
+
     /// Use hash functions to detect file changes
     #[serde(default)]
     pub check_hash: bool,
+
+    /// Hash function used to detect file changes (implies `check_hash`, default: `sha256`)
+    #[serde(default)]
+    pub hash_algorithm: Option<HashAlgorithm>,
+
+    /// Encoding of the input files when hashing them (default: `raw`)
+    #[serde(default)]
+    pub input_encoding: InputEncoding,
+
+    /// Skip files larger than this size in bytes (overrides the global setting)
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+
+    /// Output stream parsed for diagnostics: `stdout`, `stderr` or `both` (default)
+    #[serde(default)]
+    pub parse_stream: ParseStream,
+
+    /// Format of the diagnostics on stdout: `text` matched by the error formats (default), or `json`
+    #[serde(default)]
+    pub output: OutputKind,
+
+    /// JSON pointers to the fields of each diagnostic with `output = "json"`
+    #[serde(default)]
+    pub json_fields: JsonFields,
+
+    /// Error formats such as `%f:%l:%c: %m` applied to `parse_stream` (default: GNU style)
+    #[serde(default)]
+    pub error_format: Vec<String>,
+
+    /// Error formats for stdout, overriding `error_format` and `parse_stream`
+    #[serde(default)]
+    pub error_format_stdout: Vec<String>,
+
+    /// Error formats for stderr, overriding `error_format` and `parse_stream`
+    #[serde(default)]
+    pub error_format_stderr: Vec<String>,
+
+    /// Error formats of notes, which are attached to the preceding diagnostic
+    #[serde(default)]
+    pub note_format: Vec<String>,
+
+    /// Regexes of output lines to drop before parsing and printing, such as progress messages
+    #[serde(default)]
+    pub output_filter: Vec<String>,
+
+    /// Command line run after the linter, where an argument `{modified}` expands to the modified files
+    #[serde(default)]
+    pub post_run: Vec<String>,
+
+    /// How to install the command, printed when it is not found with `--show-install-hints`
+    #[serde(default)]
+    pub install_hint: Option<String>,
+
+    /// Command line printing the linter version, run by `multilint doctor`
+    #[serde(default)]
+    pub version_command: Vec<String>,
+
+    /// SHA-256 of the merged settings of this linter
+    #[serde(skip)]
+    pub digest: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha1,
+    Blake3,
+    Xxh3,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputEncoding {
+    /// Hashes the bytes as they are
+    #[default]
+    Raw,
+    /// Decodes UTF-8 and UTF-16 files with a byte order mark, so that adding or removing it is
+    /// not a modification
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseStream {
+    Stdout,
+    Stderr,
+    #[default]
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputKind {
+    #[default]
+    Text,
+    Json,
+}
+
+/// JSON pointers, such as `/location/row`, locating the fields in a diagnostic object
+///
+/// The defaults match the diagnostics printed by `--format ndjson`. An empty pointer leaves the
+/// field unset.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct JsonFields {
+    pub file: String,
+    pub line: String,
+    pub column: String,
+    pub end_line: String,
+    pub end_column: String,
+    pub message: String,
+    pub severity: String,
+}
+
+impl Default for JsonFields {
+    fn default() -> Self {
+        Self {
+            file: "/file".to_string(),
+            line: "/line".to_string(),
+            column: "/column".to_string(),
+            end_line: "/end_line".to_string(),
+            end_column: "/end_column".to_string(),
+            message: "/message".to_string(),
+            severity: "/severity".to_string(),
+        }
+    }
+}
+
+impl JsonFields {
+    /// Pointers by field name, for validation
+    pub fn pointers(&self) -> [(&'static str, &str); 7] {
+        [
+            ("file", &self.file),
+            ("line", &self.line),
+            ("column", &self.column),
+            ("end_line", &self.end_line),
+            ("end_column", &self.end_column),
+            ("message", &self.message),
+            ("severity", &self.severity),
+        ]
+    }
 }
 
 pub fn from_path(path: impl AsRef<Path>) -> Result<Root> {
-    // traverse from the root to the path and merge all config files
-    let config_files = {
-        let mut config_files = Vec::new();
-        let mut path = path.as_ref();
+    load(path.as_ref()).map_err(MultilintError::Config)
+}
+
+/// Loads exactly the config file, without looking for others in the parent directories
+pub fn from_file(path: impl AsRef<Path>) -> Result<Root> {
+    load_file(path.as_ref()).map_err(MultilintError::Config)
+}
+
+/// Loads the config file if given, or else the config files found from the path
+pub fn from_file_or_path(file: Option<&Path>, path: impl AsRef<Path>) -> Result<Root> {
+    match file {
+        Some(file) => from_file(file),
+        None => from_path(path),
+    }
+}
+
+fn load_file(path: &Path) -> anyhow::Result<Root> {
+    ensure!(
+        path.is_file(),
+        "Config file \"{}\" does not exist",
+        path.to_string_lossy()
+    );
+    let mut pattern_files = vec![];
+    let config = read_extended(path, &mut vec![], &mut pattern_files)?;
+    build(vec![(path.to_owned(), config)], pattern_files)
+}
+
+fn load(path: &Path) -> anyhow::Result<Root> {
+    // traverse from the path to the root and merge all config files
+    let mut pattern_files = vec![];
+    let configs = {
+        let mut configs = Vec::new();
+        let mut root_markers: Option<Vec<String>> = None;
+        let mut path = path;
         loop {
             let config_file = path.join("multilint.toml");
+            let mut is_root = false;
             if config_file.exists() {
-                config_files.push(config_file);
+                let config = read_extended(&config_file, &mut vec![], &mut pattern_files)?;
+                is_root = config
+                    .get("root")
+                    .and_then(toml::Value::as_bool)
+                    .unwrap_or(false);
+                if root_markers.is_none() {
+                    // the nearest config defines the markers
+                    root_markers = global_strings(&config, "root_markers");
+                }
+                configs.push((config_file, config));
+            }
+            if is_root {
+                debug!("root config found in {}", path.display());
+                break;
+            }
+            if let Some(marker) = root_markers
+                .iter()
+                .flatten()
+                .find(|marker| path.join(marker).exists())
+            {
+                debug!("root marker {} found in {}", marker, path.display());
+                break;
             }
             if path.parent().is_none() {
                 break;
             }
             path = path.parent().unwrap();
         }
-        config_files.reverse();
-        config_files
+        configs.reverse();
+        configs
     };
+    build(configs, pattern_files)
+}
 
+/// Merges the config files, from the outermost, then their fragments, and parses the result
+fn build(
+    mut configs: Vec<(PathBuf, toml::Table)>,
+    mut pattern_files: Vec<PathBuf>,
+) -> anyhow::Result<Root> {
     let mut merged = toml::Value::Table(toml::Table::new());
-    for config_file in &config_files {
-        let text = read_to_string(config_file)
-            .with_context(|| format!("Cannot read config \"{}\"", config_file.to_string_lossy()))?;
-        let value: toml::Table = toml::from_str(&text).with_context(|| {
-            format!("Cannot parse config \"{}\"", config_file.to_string_lossy())
-        })?;
-        merge(&mut merged, &toml::Value::Table(value));
+    let mut allowed: Option<(Vec<String>, PathBuf)> = None;
+    // fragments are checked against the linters allowed for the file including them
+    let mut fragments = BTreeMap::new();
+    for (config_file, config) in &configs {
+        check_allowed(config_file, config, allowed.as_ref())?;
+        for fragment in find_fragments(config_file, config)? {
+            fragments.entry(fragment).or_insert_with(|| allowed.clone());
+        }
+        if let Some(mut list) = global_strings(config, "allow_linters") {
+            // nested configs can only narrow the list
+            if let Some((allowed, _)) = &allowed {
+                list.retain(|name| allowed.contains(name));
+            }
+            allowed = Some((list, config_file.clone()));
+        }
+        merge_config(&mut merged, config);
+    }
+    for (fragment, allowed) in fragments {
+        debug!("merging fragment {}", fragment.display());
+        let config = read_extended(&fragment, &mut vec![], &mut pattern_files)?;
+        check_allowed(&fragment, &config, allowed.as_ref())?;
+        merge_config(&mut merged, &config);
+        configs.push((fragment, config));
     }
 
+    apply_templates(&mut merged)?;
+
     let merged_text = toml::to_string(&merged)?;
-    toml::from_str(&merged_text).context("Cannot parse config")
+    let mut unknown_fields = Vec::new();
+    let mut root: Root =
+        serde_ignored::deserialize(toml::Deserializer::new(&merged_text), |path| {
+            debug!("unknown field {}", path);
+            unknown_fields.push(path.to_string());
+        })
+        .context("Cannot parse config")?;
+    root.digest = digest(&merged_text);
+    root.unknown_fields = unknown_fields;
+    root.files = configs.into_iter().map(|(path, _)| path).collect();
+    root.files.extend(pattern_files);
+    for (name, linter) in &mut root.linter {
+        if let Some(value) = merged.get("linter").and_then(|linters| linters.get(name)) {
+            linter.digest = digest(&toml::to_string(value)?);
+        }
+    }
+    validate(&root)?;
+    Ok(root)
+}
+
+/// Merges the templates named by `template` beneath the linters, and removes the templates
+fn apply_templates(merged: &mut toml::Value) -> anyhow::Result<()> {
+    let templates = match merged
+        .as_table_mut()
+        .and_then(|root| root.remove("template"))
+    {
+        Some(toml::Value::Table(templates)) => templates,
+        Some(_) => bail!("template must be a table"),
+        None => toml::Table::new(),
+    };
+    let linters = merged
+        .get_mut("linter")
+        .and_then(toml::Value::as_table_mut)
+        .into_iter()
+        .flat_map(|linters| linters.iter_mut());
+    for (name, linter) in linters {
+        let Some(template) = linter.as_table_mut().and_then(|l| l.remove("template")) else {
+            continue;
+        };
+        let template = template
+            .as_str()
+            .with_context(|| format!("linter.{}.template must be a string", name))?;
+        let mut resolved = resolve_template(&templates, template, &mut vec![])
+            .with_context(|| format!("Cannot apply the template of linter \"{}\"", name))?;
+        merge_linter(&mut resolved, linter);
+        *linter = resolved;
+    }
+    Ok(())
+}
+
+/// Returns the template with the templates it names merged beneath it
+fn resolve_template(
+    templates: &toml::Table,
+    name: &str,
+    stack: &mut Vec<String>,
+) -> anyhow::Result<toml::Value> {
+    if let Some(i) = stack.iter().position(|n| n == name) {
+        bail!("Cyclic templates: {} -> {}", stack[i..].join(" -> "), name);
+    }
+    let mut template = templates
+        .get(name)
+        .and_then(toml::Value::as_table)
+        .cloned()
+        .with_context(|| format!("Template \"{}\" is not defined", name))?;
+    let mut resolved = toml::Value::Table(toml::Table::new());
+    if let Some(base) = template.remove("template") {
+        let base = base
+            .as_str()
+            .with_context(|| format!("template.{}.template must be a string", name))?;
+        stack.push(name.to_string());
+        resolved = resolve_template(templates, base, stack)?;
+        stack.pop();
+    }
+    merge_linter(&mut resolved, &toml::Value::Table(template));
+    Ok(resolved)
+}
+
+fn check_allowed(
+    config_file: &Path,
+    config: &toml::Table,
+    allowed: Option<&(Vec<String>, PathBuf)>,
+) -> anyhow::Result<()> {
+    let Some((allowed, allowed_by)) = allowed else {
+        return Ok(());
+    };
+    let linters = config.get("linter").and_then(toml::Value::as_table);
+    if let Some(name) = linters
+        .into_iter()
+        .flat_map(|linters| linters.keys())
+        .find(|name| !allowed.contains(name))
+    {
+        bail!(
+            "Linter \"{}\" in \"{}\" is not allowed by global.allow_linters in \"{}\"",
+            name,
+            config_file.to_string_lossy(),
+            allowed_by.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+/// Finds the files matching `global.include_configs` of a config file, relative to its directory
+fn find_fragments(config_file: &Path, config: &toml::Table) -> anyhow::Result<Vec<PathBuf>> {
+    let patterns = global_strings(config, "include_configs").unwrap_or_default();
+    let dir = match config_file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut fragments = Vec::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_start_matches('/');
+        // globs are anchored to the directory of the config file
+        let matcher = OverrideBuilder::new(dir)
+            .add(&format!("/{}", pattern))
+            .and_then(|builder| builder.build())
+            .with_context(|| {
+                format!(
+                    "Invalid include_configs \"{}\" in \"{}\"",
+                    pattern,
+                    config_file.to_string_lossy()
+                )
+            })?;
+        // walk only below the directories before the first wildcard
+        let mut start = dir.to_path_buf();
+        let components: Vec<&str> = pattern.split('/').collect();
+        for component in &components[..components.len() - 1] {
+            if component.contains(['*', '?', '[', '{']) {
+                break;
+            }
+            start.push(component);
+        }
+        if !start.is_dir() {
+            continue;
+        }
+        for entry in WalkBuilder::new(&start).standard_filters(false).build() {
+            let entry = entry?;
+            if entry.file_type().is_some_and(|t| t.is_file())
+                && matcher.matched(entry.path(), false).is_whitelist()
+                && entry.path() != dir.join(config_file.file_name().unwrap_or_default())
+            {
+                fragments.push(entry.into_path());
+            }
+        }
+    }
+    Ok(fragments)
+}
+
+// tables are sorted by key, so the text does not depend on the layout of the files
+fn digest(merged_text: &str) -> String {
+    hex(&Sha256::digest(merged_text))
+}
+
+/// Encodes the bytes of a digest in lowercase hexadecimal
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn validate(root: &Root) -> anyhow::Result<()> {
+    ensure!(
+        root.global.jobs != Some(0),
+        "global.jobs must be at least 1"
+    );
+    for (tag, limit) in &root.global.group_limits {
+        ensure!(*limit > 0, "global.group_limits.{} must be at least 1", tag);
+    }
+    for (name, linter) in &root.linter {
+        match (&linter.shell, linter.command.is_empty()) {
+            (Some(_), false) => bail!("linter.{} sets both command and shell", name),
+            (None, true) => bail!("linter.{} sets neither command nor shell", name),
+            _ => {}
+        }
+        if linter.shell.is_some() {
+            // the shell command line takes the place of both
+            ensure!(
+                linter.options.is_empty(),
+                "linter.{} sets both options and shell",
+                name
+            );
+            ensure!(
+                !linter.argfile,
+                "linter.{} sets both argfile and shell",
+                name
+            );
+        }
+    }
+    Ok(())
+}
+
+fn global_strings(config: &toml::Table, key: &str) -> Option<Vec<String>> {
+    config
+        .get("global")
+        .and_then(|global| global.get(key))
+        .and_then(toml::Value::as_array)
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect()
+        })
+}
+
+/// Reads a config file with the files it extends merged beneath it, adding the pattern files
+/// they name to `pattern_files`
+fn read_extended(
+    config_file: &Path,
+    stack: &mut Vec<PathBuf>,
+    pattern_files: &mut Vec<PathBuf>,
+) -> anyhow::Result<toml::Table> {
+    let mut config = read(config_file, pattern_files)?;
+    let bases: Vec<String> = match config.remove("extends") {
+        Some(toml::Value::Array(bases)) => bases
+            .into_iter()
+            .filter_map(|base| base.as_str().map(str::to_string))
+            .collect(),
+        Some(_) => bail!(
+            "extends in \"{}\" must be an array",
+            config_file.to_string_lossy()
+        ),
+        None => return Ok(config),
+    };
+
+    let canonical = config_file
+        .canonicalize()
+        .with_context(|| format!("Cannot read config \"{}\"", config_file.to_string_lossy()))?;
+    if let Some(i) = stack.iter().position(|f| f == &canonical) {
+        let cycle: Vec<_> = stack[i..]
+            .iter()
+            .chain([&canonical])
+            .map(|f| f.to_string_lossy())
+            .collect();
+        bail!("Cyclic extends: {}", cycle.join(" -> "));
+    }
+    stack.push(canonical);
+
+    let dir = config_file.parent().unwrap_or_else(|| Path::new(""));
+    let mut merged = toml::Value::Table(toml::Table::new());
+    for base in bases {
+        let mut base = read_extended(&dir.join(base), stack, pattern_files)?;
+        // only the extending file decides where the search stops
+        base.remove("root");
+        merge_config(&mut merged, &base);
+    }
+    merge_config(&mut merged, &config);
+    stack.pop();
+
+    match merged {
+        toml::Value::Table(merged) => Ok(merged),
+        _ => unreachable!(),
+    }
+}
+
+fn read(config_file: &Path, pattern_files: &mut Vec<PathBuf>) -> anyhow::Result<toml::Table> {
+    let text = read_to_string(config_file)
+        .with_context(|| format!("Cannot read config \"{}\"", config_file.to_string_lossy()))?;
+    let mut config: toml::Table = toml::from_str(&text)
+        .with_context(|| format!("Cannot parse config \"{}\"", config_file.to_string_lossy()))?;
+    read_pattern_files(config_file, &mut config, pattern_files)?;
+    Ok(config)
+}
+
+/// Appends the patterns listed in the files named by `includes_from` and `excludes_from` of the
+/// linters and templates to their `includes` and `excludes`
+///
+/// The files are relative to the config file, with a pattern per line. Blank lines and lines
+/// starting with `#` are skipped, while other lines are kept as is, such as trailing spaces
+/// escaped as in `.gitignore`. The files read are added to `pattern_files`.
+fn read_pattern_files(
+    config_file: &Path,
+    config: &mut toml::Table,
+    pattern_files: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let dir = config_file.parent().unwrap_or_else(|| Path::new(""));
+    let tables = config
+        .iter_mut()
+        .filter(|(key, _)| ["linter", "template"].contains(&key.as_str()))
+        .filter_map(|(key, tables)| Some((key, tables.as_table_mut()?)))
+        .flat_map(|(key, tables)| tables.iter_mut().map(move |(name, t)| (key, name, t)));
+    for (key, name, table) in tables {
+        let Some(table) = table.as_table_mut() else {
+            continue;
+        };
+        for (from, field) in [("includes_from", "includes"), ("excludes_from", "excludes")] {
+            let Some(files) = table.remove(from) else {
+                continue;
+            };
+            let files = files
+                .as_array()
+                .and_then(|files| {
+                    files
+                        .iter()
+                        .map(toml::Value::as_str)
+                        .collect::<Option<Vec<_>>>()
+                })
+                .with_context(|| {
+                    format!("{}.{}.{} must be an array of strings", key, name, from)
+                })?;
+            let patterns = table
+                .entry(field)
+                .or_insert_with(|| toml::Value::Array(vec![]))
+                .as_array_mut()
+                .with_context(|| format!("{}.{}.{} must be an array", key, name, field))?;
+            for file in files {
+                let path = dir.join(file);
+                let text = read_to_string(&path).with_context(|| {
+                    format!("Cannot read {} \"{}\"", from, path.to_string_lossy())
+                })?;
+                if !pattern_files.contains(&path) {
+                    pattern_files.push(path);
+                }
+                patterns.extend(
+                    text.split_inclusive('\n')
+                        .map(|line| line.trim_end_matches(['\r', '\n']))
+                        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                        .map(|line| toml::Value::String(line.to_string())),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Merges a config over the configs merged so far, overriding how the linters it sets are run
+fn merge_config(merged: &mut toml::Value, config: &toml::Table) {
+    let linters = config.get("linter").and_then(toml::Value::as_table);
+    for (name, linter) in linters.into_iter().flatten() {
+        let inherited = merged
+            .get_mut("linter")
+            .and_then(|linters| linters.get_mut(name));
+        if let Some(inherited) = inherited {
+            merge_linter(inherited, linter);
+        }
+    }
+    merge(merged, &toml::Value::Table(config.clone()));
+}
+
+/// Merges the settings of a linter or template over inherited ones
+///
+/// A `shell` replaces an inherited `command` with its `options` and `argfile`, and a `command`
+/// replaces an inherited `shell`, since a linter cannot set both.
+fn merge_linter(inherited: &mut toml::Value, linter: &toml::Value) {
+    if let Some(inherited) = inherited.as_table_mut() {
+        if linter.get("shell").is_some() {
+            for key in ["command", "options", "argfile"] {
+                inherited.remove(key);
+            }
+        }
+        if linter.get("command").is_some() {
+            inherited.remove("shell");
+        }
+    }
+    merge(inherited, linter);
 }
 
 fn merge(merged: &mut toml::Value, value: &toml::Value) {
@@ -116,7 +821,8 @@ fn merge(merged: &mut toml::Value, value: &toml::Value) {
 
 #[cfg(test)]
 mod tests {
-    use super::from_path;
+    use super::{from_file, from_path};
+    use crate::error::MultilintError;
     use std::{fs::create_dir_all, fs::File, io::Write};
     use tempfile::tempdir;
     use test_log::test;
@@ -146,4 +852,474 @@ mod tests {
         assert_eq!(config.linter["test"].command, "false");
         assert_eq!(config.linter["test"].includes, vec!["*"]);
     }
+
+    #[test]
+    fn file() {
+        let root = tempdir().unwrap();
+        let subdir = root.path().join("subdir");
+        create_dir_all(&subdir).unwrap();
+
+        {
+            let mut config = File::create(root.path().join("multilint.toml")).unwrap();
+            writeln!(config, "[linter.parent]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+
+        let path = subdir.join("ci.toml");
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'false'").unwrap();
+        }
+
+        let config = from_file(&path).unwrap();
+        assert_eq!(config.linter.keys().collect::<Vec<_>>(), vec!["test"]);
+        assert_eq!(config.files, vec![path]);
+
+        let err = from_file(subdir.join("missing.toml")).unwrap_err();
+        assert!(matches!(err, MultilintError::Config(_)));
+        assert!(err.to_string().contains("does not exist"));
+    }
+
+    #[test]
+    fn root() {
+        let root = tempdir().unwrap();
+        let subdir = root.path().join("subdir");
+        create_dir_all(&subdir).unwrap();
+
+        {
+            let path = root.path().join("multilint.toml");
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.parent]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+
+        {
+            let path = subdir.join("multilint.toml");
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "root = true").unwrap();
+            writeln!(config, "[linter.child]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+
+        let config = from_path(&subdir).unwrap();
+        assert!(config.linter.contains_key("child"));
+        assert!(!config.linter.contains_key("parent"));
+    }
+
+    #[test]
+    fn root_markers() {
+        let root = tempdir().unwrap();
+        let project = root.path().join("project");
+        let subdir = project.join("subdir");
+        create_dir_all(project.join(".git")).unwrap();
+        create_dir_all(&subdir).unwrap();
+
+        {
+            let path = root.path().join("multilint.toml");
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.outside]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+
+        {
+            let path = project.join("multilint.toml");
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.project]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+
+        {
+            let path = subdir.join("multilint.toml");
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[global]").unwrap();
+            writeln!(config, "root_markers = ['.git']").unwrap();
+        }
+
+        let config = from_path(&subdir).unwrap();
+        assert!(config.linter.contains_key("project"));
+        assert!(!config.linter.contains_key("outside"));
+    }
+
+    #[test]
+    fn allow_linters() {
+        let root = tempdir().unwrap();
+        let subdir = root.path().join("subdir");
+        create_dir_all(&subdir).unwrap();
+
+        {
+            let path = root.path().join("multilint.toml");
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[global]").unwrap();
+            writeln!(config, "allow_linters = ['test']").unwrap();
+            writeln!(config, "[linter.other]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+
+        let path = subdir.join("multilint.toml");
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+        assert!(from_path(&subdir).unwrap().linter.contains_key("test"));
+
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[global]").unwrap();
+            writeln!(config, "allow_linters = ['test', 'extra']").unwrap();
+            writeln!(config, "[linter.extra]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+        let err = from_path(&subdir).unwrap_err();
+        assert!(matches!(err, MultilintError::Config(_)));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Linter \"extra\" in \"{}\" is not allowed by global.allow_linters in \"{}\"",
+                path.display(),
+                root.path().join("multilint.toml").display()
+            )
+        );
+    }
+
+    #[test]
+    fn group_limits() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("multilint.toml");
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[global]").unwrap();
+            writeln!(config, "group_limits = {{ heavy = 2 }}").unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "tags = ['heavy']").unwrap();
+        }
+        let config = from_path(root.path()).unwrap();
+        assert_eq!(config.global.group_limits["heavy"], 2);
+        assert_eq!(config.linter["test"].tags, vec!["heavy"]);
+
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[global]").unwrap();
+            writeln!(config, "group_limits = {{ heavy = 0 }}").unwrap();
+        }
+        let err = from_path(root.path()).unwrap_err();
+        assert!(matches!(err, MultilintError::Config(_)));
+        assert_eq!(
+            err.to_string(),
+            "global.group_limits.heavy must be at least 1"
+        );
+    }
+
+    #[test]
+    fn extends() {
+        let root = tempdir().unwrap();
+        let shared = root.path().join("shared");
+        let project = root.path().join("project");
+        create_dir_all(&shared).unwrap();
+        create_dir_all(&project).unwrap();
+
+        {
+            let mut config = File::create(shared.join("base.toml")).unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "includes = ['*']").unwrap();
+        }
+
+        let path = project.join("multilint.toml");
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "root = true").unwrap();
+            writeln!(config, "extends = ['../shared/base.toml']").unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'false'").unwrap();
+        }
+        let config = from_path(&project).unwrap();
+        assert_eq!(config.linter["test"].command, "false");
+        assert_eq!(config.linter["test"].includes, vec!["*"]);
+
+        {
+            let mut config = File::create(shared.join("base.toml")).unwrap();
+            writeln!(config, "extends = ['../project/multilint.toml']").unwrap();
+        }
+        let err = from_path(&project).unwrap_err();
+        assert!(matches!(err, MultilintError::Config(_)));
+        assert!(err.to_string().starts_with("Cyclic extends: "));
+    }
+
+    #[test]
+    fn pattern_files() {
+        let root = tempdir().unwrap();
+        let lists = root.path().join("lists");
+        create_dir_all(&lists).unwrap();
+        std::fs::write(
+            lists.join("rust.txt"),
+            "# sources\n*.rs\r\n\n  \nbuild.rs\\ \n",
+        )
+        .unwrap();
+        std::fs::write(lists.join("generated.txt"), "gen/\n").unwrap();
+        {
+            let mut config = File::create(root.path().join("multilint.toml")).unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "includes = ['*.toml']").unwrap();
+            writeln!(config, "includes_from = ['lists/rust.txt']").unwrap();
+            writeln!(config, "excludes_from = ['lists/generated.txt']").unwrap();
+        }
+        let config = from_path(&root).unwrap();
+        assert_eq!(
+            config.linter["test"].includes,
+            vec!["*.toml", "*.rs", "build.rs\\ "]
+        );
+        assert_eq!(config.linter["test"].excludes, vec!["gen/"]);
+        assert!(config.unknown_fields.is_empty());
+        assert_eq!(
+            config.files,
+            vec![
+                root.path().join("multilint.toml"),
+                lists.join("rust.txt"),
+                lists.join("generated.txt")
+            ]
+        );
+
+        {
+            let mut config = File::create(root.path().join("multilint.toml")).unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "includes_from = ['missing.txt']").unwrap();
+        }
+        let err = from_path(&root).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Cannot read includes_from"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn include_configs() {
+        let root = tempdir().unwrap();
+        let fragments = root.path().join("lint.d");
+        create_dir_all(fragments.join("nested")).unwrap();
+        let write = |path: &std::path::Path, lines: &[&str]| {
+            let mut config = File::create(path).unwrap();
+            for line in lines {
+                writeln!(config, "{}", line).unwrap();
+            }
+        };
+
+        write(
+            &root.path().join("multilint.toml"),
+            &[
+                "[global]",
+                "include_configs = ['lint.d/*.toml']",
+                "[linter.a]",
+                "command = 'root'",
+                "includes = ['*']",
+            ],
+        );
+        write(&fragments.join("b.toml"), &["[linter.a]", "command = 'b'"]);
+        write(
+            &fragments.join("a.toml"),
+            &["[linter.a]", "command = 'a'", "[linter.c]", "command = 'c'"],
+        );
+        write(
+            &fragments.join("nested/d.toml"),
+            &["[linter.d]", "command = 'd'"],
+        );
+        write(&fragments.join("e.txt"), &["[linter.e]", "command = 'e'"]);
+
+        let config = from_path(root.path()).unwrap();
+        assert_eq!(config.linter["a"].command, "b");
+        assert_eq!(config.linter["a"].includes, vec!["*"]);
+        assert_eq!(config.linter["c"].command, "c");
+        assert_eq!(config.linter.len(), 2);
+        assert_eq!(
+            config.files,
+            vec![
+                root.path().join("multilint.toml"),
+                fragments.join("a.toml"),
+                fragments.join("b.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn shell() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("multilint.toml");
+        let write = |lines: &[&str]| {
+            let mut config = File::create(&path).unwrap();
+            for line in lines {
+                writeln!(config, "{}", line).unwrap();
+            }
+        };
+
+        write(&["[linter.a]", "shell = 'cd src && cargo fmt --'"]);
+        let config = from_path(root.path()).unwrap();
+        assert_eq!(
+            config.linter["a"].shell.as_deref(),
+            Some("cd src && cargo fmt --")
+        );
+
+        write(&["[linter.a]", "command = 'true'", "shell = 'true'"]);
+        let err = format!("{:#}", from_path(root.path()).unwrap_err());
+        assert_eq!(err, "linter.a sets both command and shell");
+
+        write(&["[linter.a]", "includes = ['*']"]);
+        let err = format!("{:#}", from_path(root.path()).unwrap_err());
+        assert_eq!(err, "linter.a sets neither command nor shell");
+
+        write(&["[linter.a]", "shell = 'true'", "options = ['-v']"]);
+        let err = format!("{:#}", from_path(root.path()).unwrap_err());
+        assert_eq!(err, "linter.a sets both options and shell");
+
+        write(&["[linter.a]", "shell = 'true'", "argfile = true"]);
+        let err = format!("{:#}", from_path(root.path()).unwrap_err());
+        assert_eq!(err, "linter.a sets both argfile and shell");
+
+        // a nested config switches between command and shell
+        let subdir = root.path().join("web");
+        create_dir_all(&subdir).unwrap();
+        write(&[
+            "[linter.a]",
+            "command = 'eslint'",
+            "options = ['--fix']",
+            "argfile = true",
+        ]);
+        let nested = subdir.join("multilint.toml");
+        File::create(&nested)
+            .unwrap()
+            .write_all(b"[linter.a]\nshell = 'npx eslint'\n")
+            .unwrap();
+        let config = from_path(&subdir).unwrap();
+        assert_eq!(config.linter["a"].shell.as_deref(), Some("npx eslint"));
+        assert!(config.linter["a"].command.is_empty());
+        assert!(config.linter["a"].options.is_empty());
+        assert!(!config.linter["a"].argfile);
+
+        write(&["[linter.a]", "shell = 'npx eslint'"]);
+        File::create(&nested)
+            .unwrap()
+            .write_all(b"[linter.a]\ncommand = 'eslint'\n")
+            .unwrap();
+        let config = from_path(&subdir).unwrap();
+        assert_eq!(config.linter["a"].command, "eslint");
+        assert!(config.linter["a"].shell.is_none());
+    }
+
+    #[test]
+    fn templates() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("multilint.toml");
+        let write = |lines: &[&str]| {
+            let mut config = File::create(&path).unwrap();
+            for line in lines {
+                writeln!(config, "{}", line).unwrap();
+            }
+        };
+
+        write(&[
+            "[template.base]",
+            "command = 'prettier'",
+            "options = ['--check']",
+            "[template.web]",
+            "template = 'base'",
+            "includes = ['web/**']",
+            "[linter.web]",
+            "template = 'web'",
+            "[linter.docs]",
+            "template = 'base'",
+            "includes = ['docs/*.md']",
+            "options = ['--write']",
+        ]);
+        let config = from_path(root.path()).unwrap();
+        assert!(config.unknown_fields.is_empty());
+        assert_eq!(config.linter["web"].command, "prettier");
+        assert_eq!(config.linter["web"].options, vec!["--check"]);
+        assert_eq!(config.linter["web"].includes, vec!["web/**"]);
+        assert_eq!(config.linter["docs"].command, "prettier");
+        assert_eq!(config.linter["docs"].options, vec!["--write"]);
+        assert_eq!(config.linter["docs"].includes, vec!["docs/*.md"]);
+
+        write(&["[linter.a]", "template = 'missing'"]);
+        let err = format!("{:#}", from_path(root.path()).unwrap_err());
+        assert!(
+            err.contains("Template \"missing\" is not defined"),
+            "{}",
+            err
+        );
+
+        write(&[
+            "[template.x]",
+            "template = 'y'",
+            "[template.y]",
+            "template = 'x'",
+            "[linter.a]",
+            "template = 'x'",
+        ]);
+        let err = format!("{:#}", from_path(root.path()).unwrap_err());
+        assert!(err.contains("Cyclic templates: x -> y -> x"), "{}", err);
+    }
+
+    #[test]
+    fn digest() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("multilint.toml");
+        let write = |lines: &[&str]| {
+            let mut config = File::create(&path).unwrap();
+            for line in lines {
+                writeln!(config, "{}", line).unwrap();
+            }
+        };
+
+        write(&[
+            "[linter.a]",
+            "command = 'true'",
+            "[linter.b]",
+            "command = 'true'",
+        ]);
+        let config = from_path(root.path()).unwrap();
+        assert_eq!(config.digest.len(), 64);
+
+        write(&[
+            "# comment",
+            "[linter.b]",
+            "command = \"true\"",
+            "[linter.a]",
+            "command = 'true'",
+        ]);
+        let reordered = from_path(root.path()).unwrap();
+        assert_eq!(reordered.digest, config.digest);
+        assert_eq!(reordered.linter["a"].digest, config.linter["a"].digest);
+
+        write(&[
+            "[linter.a]",
+            "command = 'true'",
+            "[linter.b]",
+            "command = 'false'",
+        ]);
+        let changed = from_path(root.path()).unwrap();
+        assert_ne!(changed.digest, config.digest);
+        assert_eq!(changed.linter["a"].digest, config.linter["a"].digest);
+        assert_ne!(changed.linter["b"].digest, config.linter["b"].digest);
+    }
+
+    #[test]
+    fn parse_error() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("multilint.toml");
+        {
+            let mut config = File::create(&path).unwrap();
+            writeln!(config, "[linter.test").unwrap();
+        }
+
+        let err = from_path(root.path()).unwrap_err();
+        assert!(matches!(err, MultilintError::Config(_)));
+        assert_eq!(
+            err.to_string(),
+            format!("Cannot parse config \"{}\"", path.display())
+        );
+    }
 }
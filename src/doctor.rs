@@ -0,0 +1,139 @@
+use crate::{
+    config::{self, LinterConfig},
+    driver,
+    error::Result,
+    linter::Linter,
+};
+use std::{path::Path, process::Command};
+
+/// Result of a single check
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub description: String,
+
+    /// Why the check failed, or `None` if it passed
+    pub problem: Option<String>,
+
+    /// Whether a failure prevents the linter from running
+    pub critical: bool,
+}
+
+impl Check {
+    fn new(description: impl Into<String>, problem: Option<String>, critical: bool) -> Self {
+        Self {
+            description: description.into(),
+            problem,
+            critical,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+/// Checks whether the selected linters can be run
+pub fn diagnose(
+    config_path: impl AsRef<Path>,
+    config_file: Option<&Path>,
+    linters: Option<&[String]>,
+) -> Result<Vec<(String, Vec<Check>)>> {
+    let config = config::from_file_or_path(config_file, &config_path)?;
+    Ok(driver::select(&config, linters)
+        .into_iter()
+        .map(|(name, linter_config)| {
+            let linter = Linter::from_config(linter_config.clone(), &config.global);
+            (name, check(&linter, &linter_config))
+        })
+        .collect())
+}
+
+fn check(linter: &Linter, config: &LinterConfig) -> Vec<Check> {
+    let mut checks = vec![Check::new(
+        format!("command \"{}\" is on PATH", linter.program()),
+        (!linter.is_executable()).then(|| match &config.install_hint {
+            Some(hint) => format!("not found ({})", hint),
+            None => "not found".to_string(),
+        }),
+        true,
+    )];
+    if !config.work_dir.as_os_str().is_empty() && !config.create_work_dir {
+        checks.push(Check::new(
+            format!("work_dir \"{}\" exists", config.work_dir.display()),
+            (!config.work_dir.is_dir()).then(|| "not a directory".to_string()),
+            true,
+        ));
+    }
+    checks.push(Check::new(
+        "globs compile",
+        linter.check_globs().err().map(|e| format!("{:#}", e)),
+        true,
+    ));
+    if let Some((program, args)) = config.version_command.split_first() {
+        checks.push(Check::new(
+            format!(
+                "version command \"{}\" runs",
+                config.version_command.join(" ")
+            ),
+            run_version(program, args, &config.work_dir),
+            false,
+        ));
+    }
+    checks
+}
+
+fn run_version(program: &str, args: &[String], work_dir: &Path) -> Option<String> {
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    if work_dir.is_dir() {
+        cmd.current_dir(work_dir);
+    }
+    match cmd.output() {
+        Ok(output) if output.status.success() => None,
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match stderr.trim() {
+                "" => Some(output.status.to_string()),
+                stderr => Some(format!("{}: {}", output.status, stderr)),
+            }
+        }
+        Err(e) => Some(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diagnose;
+    use std::{fs::File, io::Write};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn diagnose_linters() {
+        let root = tempdir().unwrap();
+        {
+            let mut config = File::create(root.path().join("multilint.toml")).unwrap();
+            writeln!(config, "[linter.good]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "version_command = ['true', '--version']").unwrap();
+            writeln!(config, "[linter.bad]").unwrap();
+            writeln!(config, "command = 'multilint-missing-command'").unwrap();
+            writeln!(config, "install_hint = 'install it'").unwrap();
+            writeln!(config, "includes = ['a{{b']").unwrap();
+            writeln!(config, "work_dir = 'missing'").unwrap();
+        }
+        let results = diagnose(root.path(), None, None).unwrap();
+        assert_eq!(results.len(), 2);
+
+        let (name, checks) = &results[0];
+        assert_eq!(name, "bad");
+        assert_eq!(checks.len(), 3);
+        assert!(checks.iter().all(|c| !c.passed() && c.critical));
+        assert_eq!(checks[0].problem.as_deref(), Some("not found (install it)"));
+
+        let (name, checks) = &results[1];
+        assert_eq!(name, "good");
+        assert_eq!(checks.len(), 3);
+        assert!(checks.iter().all(|c| c.passed()));
+    }
+}
@@ -1,43 +1,857 @@
-use crate::{config, format::OutputFormat, linter::Linter};
-use anyhow::Result;
-use std::path::Path;
+use crate::{
+    config::{self, LinterConfig, Root},
+    error::{MultilintError, Result},
+    format::{OutputFormat, Summary},
+    git,
+    index::{IgnoreRules, Index},
+    linter::{Linter, Output, Stats},
+    xargs::Semaphore,
+};
+use anyhow::{anyhow, Context};
+use log::{debug, warn};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, VecDeque},
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Config file loaded instead of searching `multilint.toml` from the config path
+    pub config_file: Option<PathBuf>,
+
+    /// Glob patterns restricting the files passed to linters
+    pub changed_only_within: Vec<String>,
+
+    /// Glob patterns added to the includes of all linters that have includes
+    pub includes: Vec<String>,
+
+    /// Glob patterns added to the excludes of all linters
+    pub excludes: Vec<String>,
+
+    /// Files passed to linters instead of walking the tree, relative to the current directory
+    pub files: Vec<PathBuf>,
+
+    /// Restrict all linters to files that git reports as untracked or modified
+    pub uncommitted_only: bool,
+
+    /// Restrict all linters to files that differ from this git revision or are untracked
+    pub changed: Option<String>,
+
+    /// Restrict all linters to files modified after this file, or process all files if it is missing
+    pub newer_than: Option<PathBuf>,
+
+    /// Set the modification time of this file to the start of the run if it succeeds
+    pub touch: Option<PathBuf>,
+
+    /// Refuse to run if git reports untracked or modified files, so that fixes are not mixed with other edits
+    pub require_clean: bool,
+
+    /// Force all linters to process one file at a time
+    pub single_file: bool,
+
+    /// Detect files modified outside the inputs of each linter
+    pub check_integrity: bool,
+
+    /// Fail when files are modified outside the inputs
+    pub strict: bool,
+
+    /// Count modifications of the inputs as fixes rather than failures
+    pub fix: bool,
+
+    /// Do not fail when linters only modified files, as with a zero `--modified-exit-code`
+    pub allow_modified: bool,
+
+    /// Continue with the other linters when a linter cannot be run
+    pub keep_going: bool,
+
+    /// Fail when a linter is skipped because its command is not found
+    pub fail_on_no_command: bool,
+
+    /// Print the `install_hint` of linters whose command is not found
+    pub show_install_hints: bool,
+
+    /// Fail when a linter is skipped because no files matched
+    pub fail_on_no_file: bool,
+
+    /// Rebuild the file index from scratch (enables the index)
+    pub reindex: bool,
+
+    /// Walk the tree even if the index is enabled
+    pub no_index: bool,
+
+    /// Warn when listing the files of a linter takes longer than this
+    pub max_walk_time: Option<Duration>,
+
+    /// Number of linters run at once, overriding `global.jobs`
+    pub jobs: Option<usize>,
+
+    /// Number of child processes run at once across all linters (default: twice the CPUs)
+    pub max_procs: Option<usize>,
+
+    /// Signal sent to linters exceeding their timeout, overriding `kill_signal` of the linters
+    pub kill_signal: Option<String>,
+
+    /// Copy the output of the linters to the terminal as it is written, running one at a time
+    /// unless `prefix_lines` is set
+    pub no_capture: bool,
+
+    /// Prefix each line copied by `no_capture` with the name of the linter
+    pub prefix_lines: bool,
+
+    /// Keep the contents of the files modified by the linters in `Report::originals`
+    pub patch: bool,
+
+    /// Stop after the first linter that fails, ignoring the results of the others still running
+    pub fail_fast: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Some linter process failed
+    pub failed: bool,
+
+    /// Some linter modified files
+    pub modified: bool,
+
+    /// Linters that could not be run with `keep_going`
+    pub errors: Vec<(String, MultilintError)>,
+
+    /// Linters skipped while `fail_on_no_command` or `fail_on_no_file` is set
+    pub skipped: Vec<String>,
+
+    /// Statistics of the linters that were run
+    pub stats: Vec<(String, Stats)>,
+
+    /// Exit codes of the linters that were run
+    pub exit_codes: Vec<(String, Option<i32>)>,
+
+    /// Number of files modified by each linter in fix mode
+    pub fixed: BTreeMap<String, usize>,
+
+    /// What became of each selected linter, in execution order
+    pub results: Vec<(String, LinterResult)>,
+
+    /// Files modified by the linters
+    pub modified_files: Vec<PathBuf>,
+
+    /// Contents of the modified files before any linter ran, with `patch`
+    pub originals: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+/// Status, time and counts of a selected linter, for the summary printed by `--stats`
+#[derive(Debug, Clone)]
+pub struct LinterResult {
+    /// `ok`, `failed`, `modified`, `no file`, `no command`, `alternative` or `error`
+    pub status: &'static str,
+
+    /// Wall-clock time taken by the linter, including listing the files
+    pub duration: Duration,
+
+    /// Counts of the run, zero if the linter was skipped
+    pub stats: Stats,
+}
+
+impl Report {
+    pub fn success(&self) -> bool {
+        !self.failed && !self.modified && self.errors.is_empty() && self.skipped.is_empty()
+    }
+
+    /// Number of linters that make the run fail
+    pub fn failed_linters(&self) -> usize {
+        let failed = self
+            .results
+            .iter()
+            .filter(|(_, r)| match r.status {
+                "failed" | "error" => true,
+                // modifications are allowed unless they make the run fail
+                "modified" => self.modified,
+                _ => false,
+            })
+            .count();
+        failed + self.skipped.len()
+    }
+}
+
+/// Returns the linters that `run_linters` would run, in execution order
+pub fn selected_linters(
+    config_path: impl AsRef<Path>,
+    config_file: Option<&Path>,
+    linters: Option<&[String]>,
+) -> Result<Vec<(String, LinterConfig)>> {
+    let config = config::from_file_or_path(config_file, &config_path)?;
+    Ok(select(&config, linters))
+}
+
+/// Maps each tag of the linters to the names of the linters carrying it, in execution order
+pub fn tags(linters: &[(String, LinterConfig)]) -> BTreeMap<String, Vec<String>> {
+    let mut tags: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, linter_config) in linters {
+        for tag in &linter_config.tags {
+            let names = tags.entry(tag.clone()).or_default();
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+    }
+    tags
+}
+
+/// Returns the linters in execution order, by descending priority and then by name
+pub(crate) fn select(config: &Root, linters: Option<&[String]>) -> Vec<(String, LinterConfig)> {
+    let mut selected: Vec<_> = config
+        .linter
+        .iter()
+        .filter(|(name, _)| match linters {
+            Some(linters) => linters.contains(name),
+            None => true,
+        })
+        .map(|(name, linter_config)| (name.clone(), linter_config.clone()))
+        .collect();
+    selected.sort_by_key(|(_, linter_config)| Reverse(linter_config.priority));
+    selected
+}
+
+/// Chooses the first linter by name with an executable command in each alternative group
+///
+/// The choice ignores priorities, which only order the linters that run.
+fn select_alternatives(
+    linters: &[(String, LinterConfig)],
+    config: &Root,
+) -> BTreeMap<String, String> {
+    let mut by_name: Vec<_> = linters.iter().collect();
+    by_name.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut alternatives = BTreeMap::new();
+    for (name, linter_config) in by_name {
+        let group = match &linter_config.alternative_group {
+            Some(group) if !alternatives.contains_key(group) => group,
+            _ => continue,
+        };
+        if Linter::from_config(linter_config.clone(), &config.global).is_executable() {
+            debug!("alternative group {}: {} selected", group, name);
+            alternatives.insert(group.clone(), name.clone());
+        }
+    }
+    alternatives
+}
+
+/// Why a linter is not run
+#[derive(Debug)]
+enum Skip {
+    Alternative(String),
+    NoCommand(Option<String>),
+}
+
+/// A linter ready to run, with what is reported if it is skipped
+#[derive(Debug)]
+struct Task {
+    name: String,
+    linter: Linter,
+    tags: Vec<String>,
+    skip: Option<Skip>,
+    elapsed: Duration,
+}
+
+type TaskResult = Option<Result<Option<Output>>>;
+
+impl Task {
+    fn run(&mut self) -> TaskResult {
+        let start = Instant::now();
+        let result = match self.skip {
+            Some(_) => None,
+            None => Some(self.linter.run(".")),
+        };
+        self.elapsed = start.elapsed();
+        result
+    }
+}
+
+fn prepare(
+    config: &Root,
+    linters: Option<&[String]>,
+    options: &Options,
+    index: Option<&Arc<Index>>,
+    newer_than: Option<SystemTime>,
+    semaphore: &Arc<Semaphore>,
+) -> Vec<Task> {
+    let selected = select(config, linters);
+    let alternatives = select_alternatives(&selected, config);
+    let given = Some(Arc::new(options.files.clone())).filter(|files| !files.is_empty());
+    let mut tasks = Vec::new();
+    for (name, mut linter_config) in selected {
+        // linters without includes do not take files
+        if !linter_config.includes.is_empty() {
+            linter_config
+                .includes
+                .extend(options.includes.iter().cloned());
+        }
+        linter_config.single_file |= options.single_file;
+        linter_config.uncommitted_only |= options.uncommitted_only;
+        let alternative = linter_config
+            .alternative_group
+            .as_ref()
+            .and_then(|group| alternatives.get(group))
+            .filter(|alternative| **alternative != name)
+            .cloned();
+        let install_hint = linter_config
+            .install_hint
+            .clone()
+            .filter(|_| options.show_install_hints);
+        let tags = linter_config.tags.clone();
+        let mut linter = Linter::from_config(linter_config, &config.global);
+        linter.scope(&options.changed_only_within);
+        if let Some(index) = index {
+            linter.index(index.clone());
+        }
+        if options.check_integrity {
+            linter.check_integrity(options.strict);
+        }
+        if options.fix {
+            linter.fix();
+        }
+        if let Some(max_walk_time) = options.max_walk_time {
+            linter.max_walk_time(max_walk_time);
+        }
+        if let Some(time) = newer_than {
+            linter.newer_than(time);
+        }
+        if let Some(rev) = &options.changed {
+            linter.changed_since(rev);
+        }
+        if let Some(signal) = &options.kill_signal {
+            linter.kill_signal(signal);
+        }
+        if options.no_capture {
+            linter.stream();
+        }
+        if options.prefix_lines {
+            linter.prefix_lines(&name);
+        }
+        if options.patch {
+            linter.snapshot();
+        }
+        if let Some(given) = &given {
+            linter.given_paths(given.clone());
+        }
+        linter.semaphore(semaphore.clone());
+        let skip = match alternative {
+            Some(alternative) => Some(Skip::Alternative(alternative)),
+            None if !linter.is_executable() => Some(Skip::NoCommand(install_hint)),
+            None => None,
+        };
+        tasks.push(Task {
+            name,
+            linter,
+            tags,
+            skip,
+            elapsed: Duration::ZERO,
+        });
+    }
+    tasks
+}
+
+/// Fails if git reports untracked or modified files, and only warns outside a git work tree
+fn ensure_clean(root: impl AsRef<Path>) -> Result<()> {
+    let mut dirty: Vec<_> = match git::uncommitted(&root) {
+        Some(dirty) => dirty.into_iter().collect(),
+        None => {
+            warn!("cannot check whether the working tree is clean outside a git repository");
+            return Ok(());
+        }
+    };
+    if dirty.is_empty() {
+        return Ok(());
+    }
+    dirty.sort();
+    debug!("uncommitted: {:?}", dirty);
+    Err(MultilintError::Other(anyhow!(
+        "The working tree is not clean: {} untracked or modified file(s), such as \"{}\"",
+        dirty.len(),
+        dirty[0].display()
+    )))
+}
+
+/// What became of a linter, passed to `ResultSink::finished`
+#[derive(Debug)]
+pub enum Outcome<'a> {
+    /// The linter ran, with its output and parsed diagnostics
+    Ran(&'a Output),
+
+    /// The linter was skipped because no files matched
+    NoFile,
+
+    /// The linter was skipped because its command is not found
+    NoCommand,
+
+    /// The linter was skipped because the named linter of its alternative group runs
+    Alternative(&'a str),
+
+    /// The linter cannot be run
+    Error(&'a MultilintError),
+}
+
+impl Outcome<'_> {
+    /// Short description of the outcome
+    pub fn status(&self) -> &'static str {
+        match self {
+            Outcome::Ran(output) if output.success() => "ok",
+            Outcome::Ran(output) if output.failed() => "failed",
+            Outcome::Ran(_) => "modified",
+            Outcome::NoFile => "no file",
+            Outcome::NoCommand => "no command",
+            Outcome::Alternative(_) => "alternative",
+            Outcome::Error(_) => "error",
+        }
+    }
+}
+
+/// Receives structured results as linters finish, for frontends embedding multilint
+///
+/// Unlike `OutputFormat`, which renders the results, a sink gets them to update its own state,
+/// such as the progress and diagnostics shown by an editor.
+pub trait ResultSink {
+    /// Called before running the linter
+    fn started(&mut self, _name: &str) {}
+
+    /// Called once the linter finished or was skipped
+    fn finished(&mut self, name: &str, outcome: Outcome<'_>);
+}
+
+/// Runs the selected linters, passing their results to the format and to the sink if any
 pub fn run_linters(
     config_path: impl AsRef<Path>,
     format: &dyn OutputFormat,
+    sink: Option<&mut dyn ResultSink>,
     linters: Option<&[String]>,
-) -> Result<bool> {
-    let config = config::from_path(&config_path)?;
-    let mut ok = true;
-    for (name, linter_config) in &config.linter {
-        if let Some(linters) = linters {
-            if !linters.contains(name) {
-                continue;
+    options: &Options,
+) -> Result<Report> {
+    let start = SystemTime::now();
+    let mut report = Report::default();
+    let mut summary = Summary::default();
+    // the format finishes even if the run stops on an error
+    let result = run_tasks(
+        config_path,
+        format,
+        sink,
+        linters,
+        options,
+        &mut report,
+        &mut summary,
+    );
+    let errors = report
+        .results
+        .iter()
+        .filter(|(_, r)| r.status == "error")
+        .count();
+    let finished = format.finish(&Summary {
+        ran: report.stats.len(),
+        errors,
+        fixed: report.fixed.clone(),
+        success: result.is_ok() && report.success(),
+        failed: report.failed_linters(),
+        ..summary
+    });
+    result?;
+    finished?;
+    if let Some(path) = options.touch.as_ref().filter(|_| report.success()) {
+        touch(path, start).map_err(MultilintError::Other)?;
+    }
+    Ok(report)
+}
+
+/// Runs the selected linters into the report, counting the configs and linters in the summary
+fn run_tasks(
+    config_path: impl AsRef<Path>,
+    format: &dyn OutputFormat,
+    mut sink: Option<&mut dyn ResultSink>,
+    linters: Option<&[String]>,
+    options: &Options,
+    report: &mut Report,
+    summary: &mut Summary,
+) -> Result<()> {
+    let config = load_config(&config_path, options)?;
+    summary.configs = config.files.len();
+    if options.require_clean {
+        ensure_clean(".")?;
+    }
+    let tasks = load_tasks(&config, linters, options)?;
+    summary.selected = tasks.len();
+    // the output of linters running at once would be interleaved, unless in prefixed lines
+    let jobs = match options.no_capture && !options.prefix_lines {
+        true => 1,
+        false => options.jobs.or(config.global.jobs).unwrap_or(1),
+    };
+    if jobs <= 1 {
+        for mut task in tasks {
+            format.start(&task.name, task.linter.work_dir());
+            if let Some(sink) = sink.as_deref_mut() {
+                sink.started(&task.name);
             }
+            let result = task.run();
+            report_task(format, &mut sink, options, report, task, result)?;
+            if options.fail_fast && !report.success() {
+                break;
+            }
+        }
+    } else {
+        let limits = &config.global.group_limits;
+        run_parallel(tasks, jobs, limits, &mut sink, |sink, task, result| {
+            format.start(&task.name, task.linter.work_dir());
+            report_task(format, sink, options, report, task, result)?;
+            Ok(!options.fail_fast || report.success())
+        })?;
+    }
+    Ok(())
+}
+
+/// What a linter would do, as listed by `dry_run`
+#[derive(Debug)]
+pub enum Plan {
+    /// The command lines the linter would run, one per batch, and the directory they run in
+    Commands(Vec<Vec<OsString>>, PathBuf),
+
+    /// The linter would be skipped because no files matched
+    NoFile,
+
+    /// The linter would be skipped because its command is not found
+    NoCommand,
+
+    /// The linter would be skipped because the named linter of its alternative group runs
+    Alternative(String),
+}
+
+/// Lists what `run_linters` would run for each selected linter, without running anything
+pub fn dry_run(
+    config_path: impl AsRef<Path>,
+    linters: Option<&[String]>,
+    options: &Options,
+) -> Result<Vec<(String, Plan)>> {
+    let config = load_config(&config_path, options)?;
+    let mut plans = Vec::new();
+    for task in load_tasks(&config, linters, options)? {
+        let plan = match task.skip {
+            Some(Skip::Alternative(alternative)) => Plan::Alternative(alternative),
+            Some(Skip::NoCommand(_)) => Plan::NoCommand,
+            None => match task.linter.dry_run(".")? {
+                Some(commands) => Plan::Commands(commands, task.linter.work_dir().to_owned()),
+                None => Plan::NoFile,
+            },
+        };
+        plans.push((task.name, plan));
+    }
+    Ok(plans)
+}
+
+fn load_config(config_path: impl AsRef<Path>, options: &Options) -> Result<Root> {
+    let mut config = config::from_file_or_path(options.config_file.as_deref(), &config_path)?;
+    config
+        .global
+        .excludes
+        .extend(options.excludes.iter().cloned());
+    if config.files.is_empty() {
+        debug!(
+            "no config found in {} or its parents",
+            config_path.as_ref().display()
+        );
+    }
+    Ok(config)
+}
+
+/// Prepares the tasks of the selected linters with the index and the limits of the run
+fn load_tasks(config: &Root, linters: Option<&[String]>, options: &Options) -> Result<Vec<Task>> {
+    let index = if (config.global.index || options.reindex) && !options.no_index {
+        let rules = IgnoreRules::from_config(&config.global);
+        let index = Index::load(".", options.reindex, &rules).map_err(MultilintError::Walk)?;
+        Some(Arc::new(index))
+    } else {
+        None
+    };
+    let newer_than = options.newer_than.as_ref().and_then(|path| {
+        let modified = fs::metadata(path).and_then(|m| m.modified());
+        if let Err(e) = &modified {
+            debug!("processing all files: {}: {}", path.display(), e);
         }
-        format.start(name);
-        let linter = Linter::from_config(linter_config.clone(), &config.global);
-        if !linter.is_executable() {
-            format.no_command(name);
-            continue;
+        modified.ok()
+    });
+    let max_procs = options
+        .max_procs
+        .unwrap_or_else(|| 2 * thread::available_parallelism().map_or(1, NonZeroUsize::get));
+    let semaphore = Arc::new(Semaphore::new(max_procs));
+    Ok(prepare(
+        config,
+        linters,
+        options,
+        index.as_ref(),
+        newer_than,
+        &semaphore,
+    ))
+}
+
+/// Sets the modification time of the file, creating it if missing
+///
+/// The time is that of the start, so that files modified during the run are processed next time.
+fn touch(path: &Path, time: SystemTime) -> anyhow::Result<()> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(time))
+        .with_context(|| format!("Cannot touch \"{}\"", path.display()))
+}
+
+/// Passes the result of a task to the format, the sink and the report
+fn report_task(
+    format: &dyn OutputFormat,
+    sink: &mut Option<&mut dyn ResultSink>,
+    options: &Options,
+    report: &mut Report,
+    task: Task,
+    result: TaskResult,
+) -> Result<()> {
+    let name = task.name;
+    let mut finished = |outcome: Outcome<'_>| {
+        let stats = match &outcome {
+            Outcome::Ran(output) => output.stats().clone(),
+            _ => Stats::default(),
+        };
+        let result = LinterResult {
+            status: outcome.status(),
+            duration: task.elapsed,
+            stats,
+        };
+        report.results.push((name.clone(), result));
+        if let Some(sink) = sink.as_deref_mut() {
+            sink.finished(&name, outcome);
         }
-        match linter.run(".")? {
-            None => format.no_file(name),
-            Some(output) => {
-                format.status(name, &output)?;
-                ok &= output.success();
+    };
+    match task.skip {
+        Some(Skip::Alternative(alternative)) => {
+            format.alternative(&name, &alternative);
+            finished(Outcome::Alternative(&alternative));
+            return Ok(());
+        }
+        Some(Skip::NoCommand(install_hint)) => {
+            format.no_command(&name, install_hint.as_deref());
+            finished(Outcome::NoCommand);
+            if options.fail_on_no_command {
+                report.skipped.push(name);
             }
+            return Ok(());
         }
+        None => {}
     }
-    Ok(ok)
+    match result {
+        None | Some(Ok(None)) => {
+            format.no_file(&name);
+            if let Some(count) = task.linter.all_excluded() {
+                let message = format!(
+                    "{} file(s) match the includes, but all are excluded; the excludes may be too broad",
+                    count
+                );
+                format.warning(&name, &message);
+            }
+            finished(Outcome::NoFile);
+            if options.fail_on_no_file {
+                report.skipped.push(name.clone());
+            }
+        }
+        Some(Ok(Some(output))) => {
+            format.status(&name, &output)?;
+            if output.unstable() {
+                let message = format!(
+                    "still modified files after {} passes",
+                    output.stats().passes
+                );
+                format.warning(&name, &message);
+            }
+            finished(Outcome::Ran(&output));
+            report.failed |= output.failed();
+            report.modified |=
+                !options.allow_modified && output.has_modifications() && !output.success();
+            if options.fix && !output.modified().is_empty() {
+                report.fixed.insert(name.clone(), output.modified().len());
+            }
+            report
+                .modified_files
+                .extend(output.modified().iter().cloned());
+            for (path, contents) in output.originals() {
+                report
+                    .originals
+                    .entry(path.clone())
+                    .or_insert_with(|| contents.clone());
+            }
+            report.stats.push((name.clone(), output.stats().clone()));
+            report.exit_codes.push((name.clone(), output.exit_code()));
+        }
+        Some(Err(e)) if options.keep_going => {
+            format.error(&name, &e);
+            finished(Outcome::Error(&e));
+            report.errors.push((name.clone(), e));
+        }
+        Some(Err(e)) => {
+            finished(Outcome::Error(&e));
+            return Err(e);
+        }
+    }
+    if let Some((elapsed, files)) = task.linter.slow_walk() {
+        let message = format!(
+            "listing files took {:.1?} for {} files; consider excluding large directories in excludes or .gitignore",
+            elapsed, files
+        );
+        format.warning(&name, &message);
+    }
+    Ok(())
+}
+
+/// Tasks waiting for a worker, and the number of running tasks with each tag
+#[derive(Default)]
+struct Queue {
+    pending: VecDeque<(usize, Task)>,
+    running: BTreeMap<String, usize>,
+    cancelled: bool,
+}
+
+impl Queue {
+    /// Takes the first task whose tags are all below their group limits
+    fn take(&mut self, limits: &BTreeMap<String, usize>) -> Option<(usize, Task)> {
+        let position = self.pending.iter().position(|(_, task)| {
+            task.tags.iter().all(|tag| match limits.get(tag) {
+                Some(limit) => self.running.get(tag).copied().unwrap_or_default() < *limit,
+                None => true,
+            })
+        })?;
+        let (i, task) = self.pending.remove(position)?;
+        for tag in &task.tags {
+            *self.running.entry(tag.clone()).or_default() += 1;
+        }
+        Some((i, task))
+    }
+
+    fn release(&mut self, tags: &[String]) {
+        for tag in tags {
+            if let Some(running) = self.running.get_mut(tag) {
+                *running -= 1;
+            }
+        }
+    }
+}
+
+/// What a worker of `run_parallel` tells the thread passing the results on
+enum Message {
+    /// The worker took the named task off the queue
+    Started(String),
+
+    /// The task with the index finished
+    Finished(usize, Box<(Task, TaskResult)>),
+}
+
+/// Runs tasks on up to `jobs` threads, passing the results to `emit` in the order of the tasks
+///
+/// The sink learns about each task as soon as a worker starts it, whereas the results wait for
+/// all earlier tasks.
+fn run_parallel<F>(
+    tasks: Vec<Task>,
+    jobs: usize,
+    limits: &BTreeMap<String, usize>,
+    sink: &mut Option<&mut dyn ResultSink>,
+    mut emit: F,
+) -> Result<()>
+where
+    F: FnMut(&mut Option<&mut dyn ResultSink>, Task, TaskResult) -> Result<bool>,
+{
+    let workers = jobs.min(tasks.len());
+    let queue = Mutex::new(Queue {
+        pending: tasks.into_iter().enumerate().collect(),
+        ..Default::default()
+    });
+    let ready = Condvar::new();
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (queue, ready) = (&queue, &ready);
+            scope.spawn(move || loop {
+                let (i, mut task) = {
+                    let mut queue = queue.lock().unwrap();
+                    loop {
+                        if queue.cancelled || queue.pending.is_empty() {
+                            return;
+                        }
+                        if let Some(task) = queue.take(limits) {
+                            break task;
+                        }
+                        queue = ready.wait(queue).unwrap();
+                    }
+                };
+                debug!("started {}", task.name);
+                if sender.send(Message::Started(task.name.clone())).is_err() {
+                    return;
+                }
+                let result = task.run();
+                queue.lock().unwrap().release(&task.tags);
+                ready.notify_all();
+                if sender
+                    .send(Message::Finished(i, Box::new((task, result))))
+                    .is_err()
+                {
+                    return;
+                }
+            });
+        }
+        drop(sender);
+
+        // results are buffered until all earlier tasks are done
+        let mut done = BTreeMap::new();
+        let mut next = 0;
+        for message in receiver {
+            let (i, finished) = match message {
+                Message::Started(name) => {
+                    if let Some(sink) = sink.as_deref_mut() {
+                        sink.started(&name);
+                    }
+                    continue;
+                }
+                Message::Finished(i, finished) => (i, finished),
+            };
+            done.insert(i, *finished);
+            while let Some((task, result)) = done.remove(&next) {
+                next += 1;
+                // pending tasks are cancelled once emit fails or asks to stop
+                let result = emit(sink, task, result);
+                if !matches!(result, Ok(true)) {
+                    queue.lock().unwrap().cancelled = true;
+                    ready.notify_all();
+                    return result.map(|_| ());
+                }
+            }
+        }
+        Ok(())
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::format::TextFormat;
+    use crate::{
+        format::{OutputFormat, Summary, TextFormat},
+        linter::Output,
+    };
 
-    use super::run_linters;
-    use std::{fs::File, io::Write};
+    use super::{ensure_clean, run_linters, selected_linters, tags, Options, Outcome, ResultSink};
+    use std::{
+        cell::RefCell,
+        fs::{self, File},
+        io::Write,
+        path::Path,
+        process::Command,
+        time::{Duration, SystemTime},
+    };
     use tempfile::tempdir;
     use test_log::test;
 
@@ -53,7 +867,11 @@ mod tests {
             writeln!(config, "command = 'true'").unwrap();
             writeln!(config, "includes = ['*']").unwrap();
         }
-        assert!(run_linters(&root.path(), &format, None).unwrap());
+        assert!(
+            run_linters(root.path(), &format, None, None, &Default::default())
+                .unwrap()
+                .success()
+        );
 
         {
             let mut config = File::create(&config).unwrap();
@@ -61,7 +879,11 @@ mod tests {
             writeln!(config, "command = 'false'").unwrap();
             writeln!(config, "includes = ['*']").unwrap();
         }
-        assert!(!run_linters(&root.path(), &format, None).unwrap());
+        assert!(
+            !run_linters(root.path(), &format, None, None, &Default::default())
+                .unwrap()
+                .success()
+        );
     }
 
     #[test]
@@ -76,6 +898,537 @@ mod tests {
             writeln!(config, "command = 'false'").unwrap();
             writeln!(config, "includes = ['*']").unwrap();
         }
-        assert!(run_linters(&root.path(), &format, Some(&[])).unwrap());
+        assert!(
+            run_linters(root.path(), &format, None, Some(&[]), &Default::default())
+                .unwrap()
+                .success()
+        );
+    }
+
+    #[test]
+    fn selected() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "[linter.b]").unwrap();
+            writeln!(config, "command = 'false'").unwrap();
+        }
+        let names = |linters: Option<&[String]>| -> Vec<String> {
+            selected_linters(root.path(), None, linters)
+                .unwrap()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        assert_eq!(names(None), vec!["a", "b"]);
+        assert_eq!(names(Some(&["b".to_string()])), vec!["b"]);
+        assert!(names(Some(&[])).is_empty());
+    }
+
+    #[test]
+    fn tag_list() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.b]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "tags = ['rust', 'fast', 'rust']").unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "tags = ['rust']").unwrap();
+            writeln!(config, "[linter.c]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+        let tags = tags(&selected_linters(root.path(), None, None).unwrap());
+        assert_eq!(
+            tags.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("fast".to_string(), vec!["b".to_string()]),
+                ("rust".to_string(), vec!["a".to_string(), "b".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn finish() {
+        #[derive(Default)]
+        struct Summaries(RefCell<Vec<Summary>>);
+
+        impl OutputFormat for Summaries {
+            fn start(&self, _name: &str, _work_dir: &Path) {}
+            fn no_command(&self, _name: &str, _install_hint: Option<&str>) {}
+            fn no_file(&self, _name: &str) {}
+            fn status(&self, _name: &str, _output: &Output) -> anyhow::Result<()> {
+                Ok(())
+            }
+
+            fn finish(&self, summary: &Summary) -> anyhow::Result<()> {
+                self.0.borrow_mut().push(summary.clone());
+                Ok(())
+            }
+        }
+
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let run = |lines: &[&str], options: &Options| {
+            let mut config = File::create(&config).unwrap();
+            for line in lines {
+                writeln!(config, "{}", line).unwrap();
+            }
+            let format = Summaries::default();
+            let result = run_linters(root.path(), &format, None, None, options);
+            let summaries = format.0.into_inner();
+            assert_eq!(summaries.len(), 1);
+            (result.is_ok(), summaries[0].clone())
+        };
+
+        let invalid = ["[linter.a]", "command = 'true'", "shell = 'true'"];
+        let (ok, summary) = run(&invalid, &Default::default());
+        assert!(!ok);
+        assert!(!summary.success);
+
+        let missing = [
+            "[linter.a]",
+            "command = 'true'",
+            "includes = ['*']",
+            "work_dir = 'missing'",
+        ];
+        let (ok, summary) = run(&missing, &Default::default());
+        assert!(!ok);
+        assert!(!summary.success);
+        assert_eq!(summary.failed, 1);
+
+        let keep_going = Options {
+            keep_going: true,
+            ..Default::default()
+        };
+        let (ok, summary) = run(&missing, &keep_going);
+        assert!(ok);
+        assert!(!summary.success);
+        assert_eq!(summary.reason(), Some("no linter could be run"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn allow_modified() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let file = root.path().join("a.txt");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'sh'").unwrap();
+            writeln!(config, "options = ['-c', 'echo x >> \"$0\"']").unwrap();
+            writeln!(config, "includes = ['a.txt']").unwrap();
+        }
+        for allow_modified in [false, true] {
+            File::create(&file).unwrap();
+            let options = Options {
+                files: vec![file.clone()],
+                allow_modified,
+                ..Default::default()
+            };
+            let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+            assert_eq!(report.results[0].1.status, "modified");
+            assert_eq!(report.success(), allow_modified);
+            assert_eq!(report.failed_linters(), usize::from(!allow_modified));
+        }
+    }
+
+    #[test]
+    fn sink() {
+        #[derive(Default)]
+        struct Events(Vec<String>);
+
+        impl ResultSink for Events {
+            fn started(&mut self, name: &str) {
+                self.0.push(format!("start {}", name));
+            }
+
+            fn finished(&mut self, name: &str, outcome: Outcome<'_>) {
+                let outcome = match outcome {
+                    Outcome::Ran(output) => format!("{} diagnostics", output.parsed().len()),
+                    Outcome::NoFile => "no file".to_string(),
+                    Outcome::NoCommand => "no command".to_string(),
+                    Outcome::Alternative(selected) => format!("alternative {}", selected),
+                    Outcome::Error(e) => format!("error {}", e),
+                };
+                self.0.push(format!("{} {}", name, outcome));
+            }
+        }
+
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'echo'").unwrap();
+            writeln!(config, "options = ['a.rs:1: warning: bad']").unwrap();
+            writeln!(config, "[linter.b]").unwrap();
+            writeln!(config, "command = 'multilint-nonexistent'").unwrap();
+            writeln!(config, "[linter.c]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "includes = ['*.nonexistent']").unwrap();
+        }
+        let mut events = Events::default();
+        run_linters(
+            root.path(),
+            &format,
+            Some(&mut events),
+            None,
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            events.0,
+            vec![
+                "start a",
+                "a 1 diagnostics",
+                "start b",
+                "b no command",
+                "start c",
+                "c no file"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sink_parallel() {
+        #[derive(Default)]
+        struct Events(Vec<String>);
+
+        impl ResultSink for Events {
+            fn started(&mut self, name: &str) {
+                self.0.push(format!("start {}", name));
+            }
+
+            fn finished(&mut self, name: &str, _outcome: Outcome<'_>) {
+                self.0.push(format!("finish {}", name));
+            }
+        }
+
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'sleep'").unwrap();
+            writeln!(config, "options = ['0.5']").unwrap();
+            writeln!(config, "[linter.b]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+        let options = Options {
+            jobs: Some(2),
+            ..Default::default()
+        };
+        let mut events = Events::default();
+        run_linters(root.path(), &format, Some(&mut events), None, &options).unwrap();
+        // b starts while a is still running, but the results keep the order of the linters
+        let mut started = events.0[..2].to_vec();
+        started.sort();
+        assert_eq!(started, vec!["start a", "start b"]);
+        assert_eq!(events.0[2..], ["finish a", "finish b"]);
+    }
+
+    #[test]
+    fn keep_going() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "work_dir = 'missing'").unwrap();
+            writeln!(config, "[linter.b]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+        assert!(run_linters(root.path(), &format, None, None, &Default::default()).is_err());
+
+        let options = Options {
+            keep_going: true,
+            ..Default::default()
+        };
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert!(!report.success());
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].0, "a");
+    }
+
+    #[test]
+    fn fail_fast() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            for (name, command) in [("a", "true"), ("b", "false"), ("c", "true")] {
+                writeln!(config, "[linter.{}]", name).unwrap();
+                writeln!(config, "command = '{}'", command).unwrap();
+            }
+        }
+        let names = |jobs| {
+            let options = Options {
+                fail_fast: true,
+                jobs: Some(jobs),
+                ..Default::default()
+            };
+            let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+            assert!(report.failed);
+            report
+                .stats
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(1), vec!["a", "b"]);
+        assert_eq!(names(3), vec!["a", "b"]);
+
+        // a failing linter of higher priority stops the run first
+        {
+            let mut config = fs::OpenOptions::new().append(true).open(&config).unwrap();
+            writeln!(config, "[linter.z]").unwrap();
+            writeln!(config, "command = 'false'").unwrap();
+            writeln!(config, "priority = 1").unwrap();
+        }
+        assert_eq!(names(1), vec!["z"]);
+        assert_eq!(names(3), vec!["z"]);
+    }
+
+    #[test]
+    fn alternatives() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'multilint-missing-command'").unwrap();
+            writeln!(config, "alternative_group = 'fmt'").unwrap();
+            writeln!(config, "[linter.b]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "includes = ['*']").unwrap();
+            writeln!(config, "alternative_group = 'fmt'").unwrap();
+            writeln!(config, "[linter.c]").unwrap();
+            writeln!(config, "command = 'false'").unwrap();
+            writeln!(config, "includes = ['*']").unwrap();
+            writeln!(config, "alternative_group = 'fmt'").unwrap();
+        }
+        let options = Options {
+            fail_on_no_command: true,
+            ..Default::default()
+        };
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert!(report.success());
+        assert_eq!(report.stats.len(), 1);
+        assert_eq!(report.stats[0].0, "b");
+        assert_eq!(report.exit_codes, vec![("b".to_string(), Some(0))]);
+
+        // running first does not make c the alternative
+        let mut config = fs::OpenOptions::new().append(true).open(&config).unwrap();
+        writeln!(config, "priority = 1").unwrap();
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert_eq!(report.results[0].0, "c");
+        assert_eq!(report.exit_codes, vec![("b".to_string(), Some(0))]);
+    }
+
+    #[test]
+    fn include_exclude() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "includes = ['*.multilint-missing']").unwrap();
+            writeln!(config, "[linter.b]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+        let options = Options {
+            includes: vec!["*.toml".to_string()],
+            ..Default::default()
+        };
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert_eq!(report.stats.len(), 2);
+        assert!(report.stats[0].1.files > 0);
+        assert_eq!(report.stats[1].1.files, 0);
+
+        let options = Options {
+            includes: vec!["*.toml".to_string()],
+            excludes: vec!["*.toml".to_string()],
+            ..Default::default()
+        };
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert_eq!(report.stats.len(), 1);
+        assert_eq!(report.stats[0].0, "b");
+    }
+
+    #[test]
+    fn parallel() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            for (name, command) in [("c", "true"), ("a", "false"), ("b", "true")] {
+                writeln!(config, "[linter.{}]", name).unwrap();
+                writeln!(config, "command = '{}'", command).unwrap();
+            }
+            writeln!(config, "[linter.d]").unwrap();
+            writeln!(config, "command = 'multilint-missing-command'").unwrap();
+        }
+        let options = Options {
+            jobs: Some(3),
+            fail_on_no_command: true,
+            ..Default::default()
+        };
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert!(report.failed);
+        let names: Vec<&str> = report.stats.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+        assert_eq!(report.skipped, vec!["d"]);
+        let statuses: Vec<&str> = report.results.iter().map(|(_, r)| r.status).collect();
+        assert_eq!(statuses, vec!["failed", "ok", "ok", "no command"]);
+        assert_eq!(report.failed_linters(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn group_limits() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let lock = root.path().join("lock");
+        let format = TextFormat::default();
+
+        {
+            // fails if another linter holds the lock
+            let script = format!(
+                "mkdir '{0}' || exit 1; sleep 0.2; rmdir '{0}'",
+                lock.display()
+            );
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[global]").unwrap();
+            writeln!(config, "jobs = 4").unwrap();
+            writeln!(config, "group_limits = {{ heavy = 1 }}").unwrap();
+            for name in ["a", "b", "c"] {
+                writeln!(config, "[linter.{}]", name).unwrap();
+                writeln!(config, "command = 'sh'").unwrap();
+                writeln!(config, "options = ['-c', \"{}\"]", script).unwrap();
+                writeln!(config, "tags = ['heavy']").unwrap();
+            }
+        }
+        let report = run_linters(root.path(), &format, None, None, &Default::default()).unwrap();
+        assert!(report.success());
+        assert_eq!(report.stats.len(), 3);
+    }
+
+    #[test]
+    fn fail_on_skip() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.a]").unwrap();
+            writeln!(config, "command = 'multilint-missing-command'").unwrap();
+            writeln!(config, "[linter.b]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+            writeln!(config, "includes = ['*.multilint-missing']").unwrap();
+        }
+        assert!(
+            run_linters(root.path(), &format, None, None, &Default::default())
+                .unwrap()
+                .success()
+        );
+
+        let options = Options {
+            fail_on_no_command: true,
+            ..Default::default()
+        };
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert_eq!(report.skipped, vec!["a"]);
+
+        let options = Options {
+            fail_on_no_file: true,
+            ..Default::default()
+        };
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert_eq!(report.skipped, vec!["b"]);
+        assert!(!report.success());
+    }
+
+    #[test]
+    fn require_clean() {
+        let root = tempdir().unwrap();
+        assert!(ensure_clean(&root).is_ok());
+
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(root.path())
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        assert!(ensure_clean(&root).is_ok());
+        File::create(root.path().join("a.rs")).unwrap();
+        let err = ensure_clean(&root).unwrap_err();
+        assert!(err.to_string().contains("1 untracked or modified file(s)"));
+    }
+
+    #[test]
+    fn touch() {
+        let root = tempdir().unwrap();
+        let config = root.path().join("multilint.toml");
+        let stamp = root.path().join("stamp");
+        let format = TextFormat::default();
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'false'").unwrap();
+        }
+        let options = Options {
+            newer_than: Some(stamp.clone()),
+            touch: Some(stamp.clone()),
+            ..Default::default()
+        };
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert!(!report.success());
+        assert!(!stamp.exists());
+
+        {
+            let mut config = File::create(&config).unwrap();
+            writeln!(config, "[linter.test]").unwrap();
+            writeln!(config, "command = 'true'").unwrap();
+        }
+        let start = SystemTime::now();
+        let report = run_linters(root.path(), &format, None, None, &options).unwrap();
+        assert!(report.success());
+        let modified = fs::metadata(&stamp).unwrap().modified().unwrap();
+        assert!(modified <= SystemTime::now());
+        assert!(modified + Duration::from_secs(1) >= start);
     }
 }
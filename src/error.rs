@@ -0,0 +1,59 @@
+use std::{error, fmt};
+
+/// Errors returned by the public API
+#[derive(Debug)]
+pub enum MultilintError {
+    /// Config files cannot be read or parsed
+    Config(anyhow::Error),
+
+    /// The linter command is not found
+    CommandNotFound(String),
+
+    /// Files to be linted cannot be listed
+    Walk(anyhow::Error),
+
+    /// Other failures, such as spawning processes or writing output
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = MultilintError> = std::result::Result<T, E>;
+
+impl fmt::Display for MultilintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultilintError::Config(e) | MultilintError::Walk(e) | MultilintError::Other(e) => {
+                // the causes are exposed through `source`
+                write!(f, "{}", e)?;
+            }
+            MultilintError::CommandNotFound(command) => {
+                write!(f, "Command \"{}\" is not found", command)?;
+            }
+        }
+        // like anyhow, `{:#}` shows the causes too
+        if f.alternate() {
+            let mut source = error::Error::source(self);
+            while let Some(cause) = source {
+                write!(f, ": {}", cause)?;
+                source = cause.source();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for MultilintError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            MultilintError::Config(e) | MultilintError::Walk(e) | MultilintError::Other(e) => {
+                e.source()
+            }
+            MultilintError::CommandNotFound(_) => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for MultilintError {
+    fn from(e: anyhow::Error) -> Self {
+        MultilintError::Other(e)
+    }
+}
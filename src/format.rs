@@ -1,71 +1,539 @@
 use anyhow::Result;
-use std::io::{stderr, stdout, Write};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    env, fs,
+    io::{stderr, stdout, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use colored::*;
+use serde::Serialize;
 
-use crate::linter::Output;
+use crate::{error::MultilintError, linter::Output, parser::Parsed};
+
+mod github;
+mod junit;
+mod ndjson;
+mod progress;
+mod sarif;
+#[cfg(unix)]
+mod syslog;
+
+pub use github::GithubFormat;
+pub use junit::JunitFormat;
+pub use ndjson::NdjsonFormat;
+pub use progress::ProgressFormat;
+pub use sarif::SarifFormat;
+#[cfg(unix)]
+pub use syslog::SyslogFormat;
 
 pub trait OutputFormat {
-    fn start(&self, name: &str);
-    fn no_command(&self, name: &str);
+    /// Called before running the linter in `work_dir`
+    fn start(&self, name: &str, work_dir: &Path);
+    /// Called when the command is not found, with how to install it if known
+    fn no_command(&self, name: &str, install_hint: Option<&str>);
+
     fn no_file(&self, name: &str);
+
+    /// Called instead of running the linter because `selected` of its alternative group runs
+    fn alternative(&self, _name: &str, _selected: &str) {}
+
     fn status(&self, name: &str, output: &Output) -> Result<()>;
+
+    /// Called when the linter cannot be run and the run continues
+    fn error(&self, _name: &str, _error: &MultilintError) {}
+
+    /// Called after the linter with a problem that does not fail the run
+    fn warning(&self, _name: &str, _message: &str) {}
+
+    /// Called once after all linters have run
+    fn finish(&self, _summary: &Summary) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Overview of a run, passed to `OutputFormat::finish`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    /// Number of config files found, with the pattern files they name
+    pub configs: usize,
+
+    /// Number of selected linters
+    pub selected: usize,
+
+    /// Number of linters whose command was run
+    pub ran: usize,
+
+    /// Number of linters that could not be run
+    pub errors: usize,
+
+    /// Number of files modified by each linter in fix mode
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fixed: BTreeMap<String, usize>,
+
+    /// Whether the run passes, as the exit code reports
+    #[serde(skip)]
+    pub success: bool,
+
+    /// Number of linters that failed, modified files, could not run or were skipped with
+    /// `--fail-on-skip`
+    #[serde(skip)]
+    pub failed: usize,
+}
+
+impl Summary {
+    /// Explains why no linter ran, or `None` if any ran
+    pub fn reason(&self) -> Option<&'static str> {
+        if self.ran > 0 {
+            None
+        } else if self.errors > 0 {
+            Some("no linter could be run")
+        } else if self.configs == 0 {
+            Some("no config found")
+        } else if self.selected == 0 {
+            Some("no linters selected")
+        } else {
+            Some("all linters skipped")
+        }
+    }
+}
+
+/// Collapsible log sections of CI services
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiGroups {
+    Github,
+    Gitlab,
+}
+
+impl CiGroups {
+    /// Detects the CI service from environment variables
+    pub fn detect() -> Option<Self> {
+        if env::var_os("GITHUB_ACTIONS").is_some() {
+            Some(CiGroups::Github)
+        } else if env::var_os("GITLAB_CI").is_some() {
+            Some(CiGroups::Gitlab)
+        } else {
+            None
+        }
+    }
+
+    fn begin(&self, name: &str) {
+        match self {
+            CiGroups::Github => println!("::group::{}", name),
+            CiGroups::Gitlab => println!(
+                "\x1b[0Ksection_start:{}:{}\r\x1b[0K{}",
+                timestamp(),
+                section_name(name),
+                name
+            ),
+        }
+    }
+
+    fn end(&self, name: &str) {
+        match self {
+            CiGroups::Github => println!("::endgroup::"),
+            CiGroups::Gitlab => println!(
+                "\x1b[0Ksection_end:{}:{}\r\x1b[0K",
+                timestamp(),
+                section_name(name)
+            ),
+        }
+    }
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// GitLab accepts only alphanumerics, `_`, `.` and `-` in section names
+fn section_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "_.-".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("multilint_{}", name)
 }
 
 #[derive(Default)]
 pub struct NullFormat {}
 
 impl OutputFormat for NullFormat {
-    fn start(&self, _name: &str) {}
-    fn no_command(&self, _name: &str) {}
+    fn start(&self, _name: &str, _work_dir: &Path) {}
+    fn no_command(&self, _name: &str, _install_hint: Option<&str>) {}
     fn no_file(&self, _name: &str) {}
     fn status(&self, _name: &str, _output: &Output) -> Result<()> {
         Ok(())
     }
 }
 
+/// Prints only `PASS` or `FAIL` once all linters have run, for shell conditionals and status bars
 #[derive(Default)]
-pub struct RawFormat {}
+pub struct StatusFormat {}
 
-impl OutputFormat for RawFormat {
-    fn start(&self, _name: &str) {}
-    fn no_command(&self, _name: &str) {}
+impl OutputFormat for StatusFormat {
+    fn start(&self, _name: &str, _work_dir: &Path) {}
+    fn no_command(&self, _name: &str, _install_hint: Option<&str>) {}
+    fn no_file(&self, _name: &str) {}
+    fn status(&self, _name: &str, _output: &Output) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&self, summary: &Summary) -> Result<()> {
+        println!("{}", if summary.success { "PASS" } else { "FAIL" });
+        Ok(())
+    }
+}
+
+/// Prints only the number of failing linters once all linters have run, `0` if all pass
+#[derive(Default)]
+pub struct CountFormat {}
+
+impl OutputFormat for CountFormat {
+    fn start(&self, _name: &str, _work_dir: &Path) {}
+    fn no_command(&self, _name: &str, _install_hint: Option<&str>) {}
     fn no_file(&self, _name: &str) {}
+    fn status(&self, _name: &str, _output: &Output) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&self, summary: &Summary) -> Result<()> {
+        println!("{}", summary.failed);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct RawFormat {
+    /// Wraps each linter in a collapsible section
+    pub ci_groups: Option<CiGroups>,
+}
+
+impl OutputFormat for RawFormat {
+    fn start(&self, name: &str, _work_dir: &Path) {
+        if let Some(groups) = &self.ci_groups {
+            groups.begin(name);
+        }
+    }
+
+    fn no_command(&self, name: &str, install_hint: Option<&str>) {
+        if let Some(hint) = install_hint {
+            eprintln!("{}: {}", name, hint);
+        }
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
+    }
+
+    fn no_file(&self, name: &str) {
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
+    }
 
-    fn status(&self, _name: &str, output: &Output) -> Result<()> {
+    fn alternative(&self, name: &str, _selected: &str) {
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
+    }
+
+    fn status(&self, name: &str, output: &Output) -> Result<()> {
         stderr().write_all(output.stderr())?;
         stdout().write_all(output.stdout())?;
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
         Ok(())
     }
+
+    fn error(&self, name: &str, error: &MultilintError) {
+        eprintln!("{}: {:#}", name, error);
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
+    }
+
+    fn warning(&self, name: &str, message: &str) {
+        eprintln!("{}: warning: {}", name, message);
+    }
 }
 
 #[derive(Default)]
-pub struct TextFormat {}
+pub struct TextFormat {
+    /// Wraps each linter in a collapsible section
+    pub ci_groups: Option<CiGroups>,
+
+    /// Prints only the status line for passing linters
+    pub hide_passing_output: bool,
+
+    /// Prints a summary of the parsed diagnostics after the output
+    pub tee: bool,
+
+    /// Prints the working directory of each linter
+    pub verbose: bool,
+
+    /// Prints at most this many lines of output, writing the full output to a temporary file
+    pub inline_lines: Option<usize>,
+
+    /// Prints the number of parsed diagnostics instead of the output, unless nothing was parsed
+    pub count_problems: bool,
+
+    /// The output of the linters was shown as it was written, so only that of cached runs is printed
+    pub streamed: bool,
+
+    /// Makes the locations of diagnostics clickable with OSC 8 hyperlinks to the files
+    pub hyperlinks: bool,
+
+    /// Working directory of the running linter, which the diagnostics are relative to, set by `start`
+    pub work_dir: RefCell<PathBuf>,
+}
 
 impl OutputFormat for TextFormat {
-    fn start(&self, name: &str) {
-        print!("{} {} ... ", "Running".bold().green(), &name);
+    fn start(&self, name: &str, work_dir: &Path) {
+        *self.work_dir.borrow_mut() = work_dir.to_path_buf();
+        if let Some(groups) = &self.ci_groups {
+            groups.begin(name);
+        }
+        if self.verbose {
+            let work_dir = fs::canonicalize(work_dir).unwrap_or_else(|_| work_dir.to_owned());
+            print!(
+                "{} {} in {} ... ",
+                "Running".bold().green(),
+                &name,
+                work_dir.display()
+            );
+        } else {
+            print!("{} {} ... ", "Running".bold().green(), &name);
+        }
+        if self.streamed {
+            println!();
+        }
     }
 
-    fn no_command(&self, _name: &str) {
+    fn no_command(&self, name: &str, install_hint: Option<&str>) {
         println!("{}", "no command".yellow());
+        if let Some(hint) = install_hint {
+            println!("{}: {}", "hint".cyan(), hint);
+        }
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
     }
 
-    fn no_file(&self, _name: &str) {
+    fn no_file(&self, name: &str) {
         println!("{}", "skipped".yellow());
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
+    }
+
+    fn alternative(&self, name: &str, selected: &str) {
+        println!(
+            "{}",
+            format!("skipped (alternative {} selected)", selected).yellow()
+        );
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
     }
 
-    fn status(&self, _name: &str, output: &Output) -> Result<()> {
-        if output.success() {
-            println!("{}", "ok".green());
+    fn status(&self, name: &str, output: &Output) -> Result<()> {
+        let status = if output.timed_out() {
+            "timed out".red()
+        } else if let Some(signal) = output.signal() {
+            format!("failed (signal {})", signal).red()
+        } else if output.process_failed() {
+            "failed".red()
+        } else if output.integrity_violated() {
+            "failed (modified files outside its inputs)".red()
+        } else if output.has_modifications() {
+            "fixed".yellow()
+        } else {
+            "ok".green()
+        };
+        let count = if self.count_problems {
+            output.parsed().len()
         } else {
-            println!("{}", "failed".red());
+            0
+        };
+        let status = match count {
+            0 => status.to_string(),
+            n => format!("{}: {}", status, problems(n)),
+        };
+        let status = if output.cached() {
+            format!("{} (cached)", status)
+        } else {
+            status
+        };
+        match output.stats().passes {
+            passes if passes > 1 => println!("{} ({} passes)", status, passes),
+            _ => println!("{}", status),
+        }
+        if !(self.hide_passing_output && output.success()) {
+            let text = [output.stderr(), output.stdout()].concat();
+            match self.inline_lines.and_then(|n| split_lines(&text, n)) {
+                _ if count > 0 || (self.streamed && !output.cached()) => {}
+                Some((head, rest)) => {
+                    stdout().write_all(head)?;
+                    let (_, path) = tempfile::Builder::new()
+                        .prefix(&format!("{}-", section_name(name)))
+                        .suffix(".log")
+                        .tempfile()
+                        .and_then(|mut file| file.write_all(&text).map(|_| file))?
+                        .keep()?;
+                    let lines = if rest == 1 { "line" } else { "lines" };
+                    let message = format!("... {} more {} in {}", rest, lines, path.display());
+                    println!("{}", message.yellow());
+                }
+                None => stdout().write_all(&text)?,
+            }
+            if self.tee {
+                let work_dir = self.work_dir.borrow();
+                print_summary(output, self.hyperlinks.then_some(&*work_dir));
+            }
         }
-        stdout().write_all(output.stderr())?;
-        stdout().write_all(output.stdout())?;
         for f in output.modified() {
             println!("{}: modified", f.display());
         }
+        for f in output.modified_outside() {
+            println!("{}: {}", f.display(), "modified outside of inputs".yellow());
+        }
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
+        Ok(())
+    }
+
+    fn error(&self, name: &str, error: &MultilintError) {
+        println!("{}", "error".red());
+        println!("{:#}", error);
+        if let Some(groups) = &self.ci_groups {
+            groups.end(name);
+        }
+    }
+
+    fn warning(&self, _name: &str, message: &str) {
+        println!("{}: {}", "warning".yellow(), message);
+    }
+
+    fn finish(&self, summary: &Summary) -> Result<()> {
+        for (name, files) in &summary.fixed {
+            println!(
+                "{} {} in {}",
+                "Fixed".bold().yellow(),
+                plural(*files, "file"),
+                name
+            );
+        }
+        if let Some(reason) = summary.reason() {
+            println!("{}", format!("Nothing ran: {}", reason).yellow());
+        }
         Ok(())
     }
 }
+
+/// Splits off the first `n` lines, returning them with the number of remaining lines if any
+fn split_lines(text: &[u8], n: usize) -> Option<(&[u8], usize)> {
+    let mut newlines = text
+        .iter()
+        .enumerate()
+        .filter(|(_, &b)| b == b'\n')
+        .map(|(i, _)| i);
+    let end = if n == 0 { 0 } else { newlines.nth(n - 1)? + 1 };
+    let rest = &text[end..];
+    if rest.is_empty() {
+        return None;
+    }
+    let rest_lines = rest.iter().filter(|&&b| b == b'\n').count() + !rest.ends_with(b"\n") as usize;
+    Some((&text[..end], rest_lines))
+}
+
+/// Lists the diagnostics, linking their locations to the files under `work_dir` if given
+fn print_summary(output: &Output, work_dir: Option<&Path>) {
+    let parsed = output.parsed();
+    if parsed.is_empty() {
+        return;
+    }
+    let location = |p: &Parsed| match work_dir {
+        Some(work_dir) => hyperlink(&p.location(), &work_dir.join(&p.file), p.line),
+        None => p.location(),
+    };
+    println!("{}", problems(parsed.len()).bold());
+    for p in parsed {
+        println!("  {}: {}", location(p).cyan(), p.message);
+        for r in &p.related {
+            println!("    {}: {}", location(r).cyan(), r.message);
+        }
+    }
+}
+
+/// Wraps the text in an OSC 8 escape sequence linking to the file, with the line as the fragment
+fn hyperlink(text: &str, path: &Path, line: Option<usize>) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    if let Some(line) = line {
+        uri.push_str(&format!("#{}", line));
+    }
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", uri, text)
+}
+
+fn problems(n: usize) -> String {
+    plural(n, "problem")
+}
+
+fn plural(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("1 {}", noun),
+        n => format!("{} {}s", n, noun),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hyperlink, plural, problems, split_lines};
+    use std::path::Path;
+    use test_log::test;
+
+    #[test]
+    fn inline_lines() {
+        let text = b"a\nb\nc\nd";
+        assert_eq!(split_lines(text, 2), Some((&b"a\nb\n"[..], 2)));
+        assert_eq!(split_lines(text, 0), Some((&b""[..], 4)));
+        assert_eq!(split_lines(text, 3), Some((&b"a\nb\nc\n"[..], 1)));
+        assert_eq!(split_lines(text, 4), None);
+        assert_eq!(split_lines(b"a\nb\n", 2), None);
+    }
+
+    #[test]
+    fn problem_count() {
+        assert_eq!(problems(1), "1 problem");
+        assert_eq!(problems(3), "3 problems");
+        assert_eq!(plural(0, "file"), "0 files");
+    }
+
+    #[test]
+    fn hyperlinks() {
+        assert_eq!(
+            hyperlink("a b.rs:3", Path::new("/src/a b.rs"), Some(3)),
+            "\x1b]8;;file:///src/a%20b.rs#3\x1b\\a b.rs:3\x1b]8;;\x1b\\"
+        );
+        assert!(hyperlink("a.rs", Path::new("/a.rs"), None).contains("file:///a.rs\x1b"));
+    }
+}
@@ -0,0 +1,248 @@
+use super::{OutputFormat, Summary};
+use crate::{error::MultilintError, linter::Output};
+use anyhow::{Context, Result};
+use std::{
+    cell::RefCell,
+    env,
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{stdout, Write},
+    path::{Path, PathBuf},
+};
+
+struct Row {
+    name: String,
+    status: &'static str,
+    diagnostics: usize,
+}
+
+/// Prints GitHub Actions annotations and appends a Markdown table to the job summary
+pub struct GithubFormat {
+    summary: Option<PathBuf>,
+    rows: RefCell<Vec<Row>>,
+}
+
+impl GithubFormat {
+    /// Appends the summary to `summary`, or writes none if `None`
+    pub fn new(summary: Option<PathBuf>) -> Self {
+        Self {
+            summary,
+            rows: RefCell::new(vec![]),
+        }
+    }
+
+    /// Appends the summary to `$GITHUB_STEP_SUMMARY` if it is set
+    pub fn from_env() -> Self {
+        Self::new(env::var_os("GITHUB_STEP_SUMMARY").map(PathBuf::from))
+    }
+
+    fn push(&self, name: &str, status: &'static str, diagnostics: usize) {
+        self.rows.borrow_mut().push(Row {
+            name: name.to_string(),
+            status,
+            diagnostics,
+        });
+    }
+
+    fn summary(&self) -> String {
+        let mut markdown = String::new();
+        let _ = writeln!(markdown, "### multilint");
+        let _ = writeln!(markdown);
+        let _ = writeln!(markdown, "| Linter | Status | Diagnostics |");
+        let _ = writeln!(markdown, "| --- | --- | ---: |");
+        for row in self.rows.borrow().iter() {
+            let _ = writeln!(
+                markdown,
+                "| {} | {} | {} |",
+                row.name.replace('|', "\\|"),
+                row.status,
+                row.diagnostics
+            );
+        }
+        markdown
+    }
+}
+
+impl OutputFormat for GithubFormat {
+    fn start(&self, name: &str, _work_dir: &Path) {
+        println!("::group::{}", name);
+    }
+
+    fn no_command(&self, name: &str, install_hint: Option<&str>) {
+        println!("::endgroup::");
+        if let Some(hint) = install_hint {
+            println!("::notice title={}::{}", property(name), data(hint));
+        }
+        self.push(name, "no command", 0);
+    }
+
+    fn no_file(&self, name: &str) {
+        println!("::endgroup::");
+        self.push(name, "skipped", 0);
+    }
+
+    fn alternative(&self, name: &str, _selected: &str) {
+        println!("::endgroup::");
+        self.push(name, "alternative selected", 0);
+    }
+
+    fn status(&self, name: &str, output: &Output) -> Result<()> {
+        stdout().write_all(output.stderr())?;
+        stdout().write_all(output.stdout())?;
+        println!("::endgroup::");
+
+        let parsed = output.parsed();
+        let default_level = if output.failed() { "error" } else { "warning" };
+        for p in parsed {
+            let level = p
+                .severity
+                .as_deref()
+                .and_then(level)
+                .unwrap_or(default_level);
+            let mut properties = format!("file={}", property(&p.file));
+            if let Some(line) = p.line {
+                let _ = write!(properties, ",line={}", line);
+            }
+            if let Some(column) = p.column {
+                let _ = write!(properties, ",col={}", column);
+            }
+            println!(
+                "::{} {},title={}::{}",
+                level,
+                properties,
+                property(name),
+                data(&p.message)
+            );
+        }
+        if output.process_failed() && parsed.is_empty() {
+            // without locations, the raw output is the message
+            let text = [output.stderr(), output.stdout()].concat();
+            let text = String::from_utf8_lossy(&text);
+            let message = match text.trim() {
+                "" => format!("{} failed", name),
+                text => text.to_string(),
+            };
+            println!("::error title={}::{}", property(name), data(&message));
+        }
+        for f in output.modified() {
+            println!(
+                "::error file={},title={}::modified by {}",
+                property(&f.to_string_lossy()),
+                property(name),
+                data(name)
+            );
+        }
+
+        let status = if output.failed() {
+            "failed"
+        } else if output.has_modifications() {
+            "fixed"
+        } else {
+            "ok"
+        };
+        self.push(name, status, parsed.len());
+        Ok(())
+    }
+
+    fn error(&self, name: &str, error: &MultilintError) {
+        println!("::endgroup::");
+        println!(
+            "::error title={}::{}",
+            property(name),
+            data(&format!("{:#}", error))
+        );
+        self.push(name, "error", 0);
+    }
+
+    fn warning(&self, name: &str, message: &str) {
+        println!("::warning title={}::{}", property(name), data(message));
+    }
+
+    fn finish(&self, _summary: &Summary) -> Result<()> {
+        let path = match &self.summary {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(self.summary().as_bytes()))
+            .with_context(|| format!("Cannot write job summary \"{}\"", path.display()))
+    }
+}
+
+/// Returns the annotation level for a parsed severity
+fn level(severity: &str) -> Option<&'static str> {
+    match severity {
+        "error" | "fatal error" => Some("error"),
+        "warning" => Some("warning"),
+        "note" | "info" | "help" => Some("notice"),
+        _ => None,
+    }
+}
+
+// https://github.com/actions/toolkit/blob/main/packages/core/src/command.ts
+fn data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn property(text: &str) -> String {
+    data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data, level, property, GithubFormat};
+    use crate::{config::LinterConfig, format::OutputFormat, linter::Linter};
+    use std::fs;
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[cfg(unix)]
+    #[test]
+    fn summary() {
+        let root = tempdir().unwrap();
+        let output = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec!["-c".to_string(), "echo 'a.rs:1:2: bad'; false".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .run(&root)
+        .unwrap()
+        .unwrap();
+
+        let path = root.path().join("summary.md");
+        fs::write(&path, "before\n").unwrap();
+        let format = GithubFormat::new(Some(path.clone()));
+        format.status("a|b", &output).unwrap();
+        format.no_file("skipped");
+        format.finish(&Default::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "before\n### multilint\n\n| Linter | Status | Diagnostics |\n| --- | --- | ---: |\n\
+             | a\\|b | failed | 1 |\n| skipped | skipped | 0 |\n"
+        );
+
+        GithubFormat::new(None).finish(&Default::default()).unwrap();
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(level("warning"), Some("warning"));
+        assert_eq!(level("fatal error"), Some("error"));
+        assert_eq!(level("help"), Some("notice"));
+        assert_eq!(level("convention"), None);
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(data("100%\r\na:b,c"), "100%25%0D%0Aa:b,c");
+        assert_eq!(property("a:b,c"), "a%3Ab%2Cc");
+    }
+}
@@ -0,0 +1,368 @@
+use super::{OutputFormat, Summary};
+use crate::{error::MultilintError, linter::Output, parser::Parsed};
+use anyhow::{Context, Result};
+use std::{
+    cell::RefCell,
+    env,
+    fmt::Write as _,
+    fs,
+    io::{stdout, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, SystemTime},
+};
+
+enum State {
+    Passed,
+    Failed(&'static str, String),
+    Skipped(String),
+}
+
+struct Suite {
+    name: String,
+    time: Duration,
+    state: State,
+    /// Parsed diagnostics of a failed linter, reported as a failing test case each
+    diagnostics: Vec<Parsed>,
+}
+
+impl Suite {
+    fn tests(&self) -> usize {
+        self.diagnostics.len().max(1)
+    }
+
+    fn failures(&self) -> usize {
+        match self.state {
+            State::Failed(..) => self.tests(),
+            _ => 0,
+        }
+    }
+}
+
+/// Writes a JUnit XML report with a test suite per linter
+pub struct JunitFormat {
+    output: Option<PathBuf>,
+    timestamp: SystemTime,
+    suites: RefCell<Vec<Suite>>,
+}
+
+impl JunitFormat {
+    /// Writes the report to `output`, or stdout if `None`
+    pub fn new(output: Option<PathBuf>) -> Self {
+        Self {
+            output,
+            timestamp: SystemTime::now(),
+            suites: RefCell::new(vec![]),
+        }
+    }
+
+    fn push(&self, name: &str, time: Duration, state: State) {
+        self.push_with(name, time, state, vec![]);
+    }
+
+    fn push_with(&self, name: &str, time: Duration, state: State, diagnostics: Vec<Parsed>) {
+        self.suites.borrow_mut().push(Suite {
+            name: name.to_string(),
+            time,
+            state,
+            diagnostics,
+        });
+    }
+
+    fn report(&self) -> String {
+        let suites = self.suites.borrow();
+        let tests: usize = suites.iter().map(Suite::tests).sum();
+        let failures: usize = suites.iter().map(Suite::failures).sum();
+        let skipped = suites
+            .iter()
+            .filter(|s| matches!(s.state, State::Skipped(_)))
+            .count();
+        let time: Duration = suites.iter().map(|s| s.time).sum();
+        let timestamp = humantime::format_rfc3339_seconds(self.timestamp).to_string();
+
+        let mut xml = String::new();
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            xml,
+            r#"<testsuites name="multilint" tests="{}" failures="{}" skipped="{}" time="{:.3}" timestamp="{}" hostname="{}">"#,
+            tests,
+            failures,
+            skipped,
+            time.as_secs_f64(),
+            // JUnit timestamps carry no time zone
+            timestamp.trim_end_matches('Z'),
+            escape(&hostname()),
+        );
+        for suite in suites.iter() {
+            let name = escape(&suite.name);
+            let time = suite.time.as_secs_f64();
+            let _ = writeln!(
+                xml,
+                r#"  <testsuite name="{}" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+                name,
+                suite.tests(),
+                suite.failures(),
+                matches!(suite.state, State::Skipped(_)) as usize,
+                time,
+            );
+            // a failing test case per diagnostic, with the whole output alongside
+            let failed_output = match &suite.state {
+                State::Failed(_, text) if !suite.diagnostics.is_empty() => Some(text),
+                _ => None,
+            };
+            if let Some(text) = failed_output {
+                for p in &suite.diagnostics {
+                    let _ = writeln!(
+                        xml,
+                        r#"    <testcase name="{}" classname="{}">"#,
+                        escape(&p.location()),
+                        name
+                    );
+                    let _ = writeln!(
+                        xml,
+                        r#"      <failure message="{}"><![CDATA[{}]]></failure>"#,
+                        escape(&p.message),
+                        cdata(&related(p))
+                    );
+                    let _ = writeln!(xml, "    </testcase>");
+                }
+                let _ = writeln!(
+                    xml,
+                    "    <system-out><![CDATA[{}]]></system-out>",
+                    cdata(text)
+                );
+                let _ = writeln!(xml, "  </testsuite>");
+                continue;
+            }
+            let _ = write!(
+                xml,
+                r#"    <testcase name="{}" classname="multilint" time="{:.3}""#,
+                name, time
+            );
+            match &suite.state {
+                State::Passed => {
+                    let _ = writeln!(xml, "/>");
+                }
+                State::Failed(message, text) => {
+                    let _ = writeln!(xml, ">");
+                    let _ = writeln!(
+                        xml,
+                        r#"      <failure message="{}"><![CDATA[{}]]></failure>"#,
+                        message,
+                        cdata(text)
+                    );
+                    let _ = writeln!(xml, "    </testcase>");
+                }
+                State::Skipped(message) => {
+                    let _ = writeln!(xml, ">");
+                    let _ = writeln!(xml, r#"      <skipped message="{}"/>"#, message);
+                    let _ = writeln!(xml, "    </testcase>");
+                }
+            }
+            let _ = writeln!(xml, "  </testsuite>");
+        }
+        let _ = writeln!(xml, "</testsuites>");
+        xml
+    }
+}
+
+impl OutputFormat for JunitFormat {
+    fn start(&self, _name: &str, _work_dir: &Path) {}
+
+    fn no_command(&self, name: &str, install_hint: Option<&str>) {
+        let message = match install_hint {
+            Some(hint) => format!("no command: {}", escape(hint)),
+            None => "no command".to_string(),
+        };
+        self.push(name, Duration::ZERO, State::Skipped(message));
+    }
+
+    fn no_file(&self, name: &str) {
+        self.push(name, Duration::ZERO, State::Skipped("no file".to_string()));
+    }
+
+    fn alternative(&self, name: &str, selected: &str) {
+        let message = format!("alternative {} selected", escape(selected));
+        self.push(name, Duration::ZERO, State::Skipped(message));
+    }
+
+    fn status(&self, name: &str, output: &Output) -> Result<()> {
+        let state = if output.success() {
+            State::Passed
+        } else {
+            let mut text = String::from_utf8_lossy(output.stderr()).into_owned();
+            text.push_str(&String::from_utf8_lossy(output.stdout()));
+            for f in output.modified() {
+                let _ = writeln!(text, "{}: modified", f.display());
+            }
+            for f in output.modified_outside() {
+                let _ = writeln!(text, "{}: modified outside of inputs", f.display());
+            }
+            let message = if output.failed() {
+                "failed"
+            } else {
+                "modified"
+            };
+            State::Failed(message, text)
+        };
+        let diagnostics = match state {
+            State::Failed(..) => output.parsed().to_vec(),
+            _ => vec![],
+        };
+        self.push_with(name, output.duration(), state, diagnostics);
+        Ok(())
+    }
+
+    fn error(&self, name: &str, error: &MultilintError) {
+        self.push(
+            name,
+            Duration::ZERO,
+            State::Failed("error", format!("{:#}", error)),
+        );
+    }
+
+    // warnings do not fit in the report
+    fn finish(&self, _summary: &Summary) -> Result<()> {
+        let report = self.report();
+        match &self.output {
+            Some(path) => fs::write(path, report)
+                .with_context(|| format!("Cannot write report \"{}\"", path.display()))?,
+            None => stdout().write_all(report.as_bytes())?,
+        }
+        Ok(())
+    }
+}
+
+// the diagnostic with its notes, as the body of the failure
+fn related(parsed: &Parsed) -> String {
+    let mut text = format!("{}: {}\n", parsed.location(), parsed.message);
+    for r in &parsed.related {
+        let _ = writeln!(text, "  {}: {}", r.location(), r.message);
+    }
+    text
+}
+
+fn hostname() -> String {
+    if let Some(name) = env::var_os("COMPUTERNAME").or_else(|| env::var_os("HOSTNAME")) {
+        return name.to_string_lossy().into_owned();
+    }
+    Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+// characters that cannot appear in XML 1.0 documents, such as ANSI escapes
+fn is_valid_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars().filter(|&c| is_valid_char(c)) {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn cdata(text: &str) -> String {
+    let text: String = text.chars().filter(|&c| is_valid_char(c)).collect();
+    // split the terminator across two sections
+    text.replace("]]>", "]]]]><![CDATA[>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cdata, escape, JunitFormat};
+    use crate::{config::LinterConfig, format::OutputFormat, linter::Linter};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape(r#"<a href="x">&'"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&apos;"
+        );
+        assert_eq!(escape("\x1b[31mred\x1b[0m"), "[31mred[0m");
+        assert_eq!(cdata("a]]>b"), "a]]]]><![CDATA[>b");
+    }
+
+    #[test]
+    fn report() {
+        let root = tempdir().unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "echo".to_string(),
+                options: vec!["<&>".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let output = linter.run(&root).unwrap().unwrap();
+        let failed = Linter::from_config(
+            LinterConfig {
+                command: "false".to_string(),
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .run(&root)
+        .unwrap()
+        .unwrap();
+
+        let format = JunitFormat::new(None);
+        format.status("a&b", &output).unwrap();
+        format.status("failed", &failed).unwrap();
+        format.no_command("missing", None);
+        let report = format.report();
+        assert!(
+            report.contains(r#"<testsuites name="multilint" tests="3" failures="1" skipped="1""#)
+        );
+        assert!(report.contains(r#"<failure message="failed"><![CDATA[]]></failure>"#));
+        assert!(report.contains(r#"<testsuite name="a&amp;b" tests="1" failures="0" skipped="0""#));
+        assert!(report.contains(r#"<skipped message="no command"/>"#));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnostics() {
+        let root = tempdir().unwrap();
+        let output = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec![
+                    "-c".to_string(),
+                    r#"echo 'a<1>.rs:1:2: "bad" & worse'; echo 'b.rs:3: ]]>'; exit 1"#.to_string(),
+                ],
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .run(&root)
+        .unwrap()
+        .unwrap();
+
+        let format = JunitFormat::new(None);
+        format.status("lint", &output).unwrap();
+        let report = format.report();
+        assert!(
+            report.contains(r#"<testsuites name="multilint" tests="2" failures="2" skipped="0""#)
+        );
+        assert!(report.contains(r#"<testsuite name="lint" tests="2" failures="2" skipped="0""#));
+        assert!(report.contains(r#"<testcase name="a&lt;1&gt;.rs:1:2" classname="lint">"#));
+        assert!(report.contains(r#"<failure message="&quot;bad&quot; &amp; worse">"#));
+        assert!(report.contains(r#"<failure message="]]&gt;"><![CDATA[b.rs:3: ]]]]><![CDATA[>"#));
+        assert!(report.contains("<system-out><![CDATA[a<1>.rs:1:2"));
+    }
+}
@@ -0,0 +1,306 @@
+use super::{OutputFormat, Summary};
+use crate::{error::MultilintError, linter::Output, parser::Parsed};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+type Position = (Option<usize>, Option<usize>);
+
+/// Prints a JSON object per linter on its own line
+#[derive(Default)]
+pub struct NdjsonFormat {
+    by_file: bool,
+    work_dir: RefCell<PathBuf>,
+    files: RefCell<BTreeMap<String, Vec<(Position, Value)>>>,
+}
+
+impl NdjsonFormat {
+    /// Prints the diagnostics of all linters grouped by file at the end instead of in each record
+    pub fn group_by_file() -> Self {
+        Self {
+            by_file: true,
+            ..Default::default()
+        }
+    }
+
+    fn collect(&self, name: &str, parsed: &[Parsed]) {
+        let work_dir = self.work_dir.borrow();
+        let mut files = self.files.borrow_mut();
+        for p in parsed {
+            let mut diagnostic = json!(p);
+            diagnostic["linter"] = json!(name);
+            if let Some(diagnostic) = diagnostic.as_object_mut() {
+                diagnostic.remove("file");
+            }
+            files
+                .entry(normalize(&work_dir, &p.file))
+                .or_default()
+                .push(((p.line, p.column), diagnostic));
+        }
+    }
+
+    // a record per file, sorted by path, with the diagnostics sorted by position
+    fn file_records(&self) -> Vec<Value> {
+        let mut files = self.files.borrow_mut();
+        let files = std::mem::take(&mut *files);
+        files
+            .into_iter()
+            .map(|(file, mut diagnostics)| {
+                diagnostics.sort_by_key(|(position, _)| *position);
+                let diagnostics: Vec<Value> = diagnostics.into_iter().map(|(_, d)| d).collect();
+                json!({ "file": file, "diagnostics": diagnostics })
+            })
+            .collect()
+    }
+}
+
+/// Resolves a path reported by a linter in `work_dir` lexically, relative to the current directory
+fn normalize(work_dir: &Path, file: &str) -> String {
+    let mut path = PathBuf::new();
+    for component in work_dir.join(file).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(path.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                path.pop();
+            }
+            component => path.push(component),
+        }
+    }
+    if let Ok(cwd) = std::env::current_dir() {
+        if let Ok(relative) = path.strip_prefix(&cwd) {
+            path = relative.to_path_buf();
+        }
+    }
+    path.to_string_lossy().into_owned()
+}
+
+fn base(name: &str, status: &str) -> Value {
+    json!({ "linter": name, "status": status })
+}
+
+fn record(name: &str, output: &Output) -> Value {
+    let status = if output.failed() {
+        "failed"
+    } else if output.has_modifications() {
+        "fixed"
+    } else {
+        "ok"
+    };
+    let mut record = json!({
+        "linter": name,
+        "status": status,
+        "duration": output.duration().as_secs_f64(),
+        "modified": output.modified(),
+        "modified_outside": output.modified_outside(),
+        "stats": output.stats(),
+        "diagnostics": output.parsed(),
+    });
+    if let Some(code) = output.failure_code() {
+        record["exit_code"] = json!(code);
+    }
+    if let Some(signal) = output.signal() {
+        record["signal"] = json!(signal);
+    }
+    record
+}
+
+impl OutputFormat for NdjsonFormat {
+    fn start(&self, _name: &str, work_dir: &Path) {
+        *self.work_dir.borrow_mut() = work_dir.to_path_buf();
+    }
+
+    fn no_command(&self, name: &str, install_hint: Option<&str>) {
+        let mut record = base(name, "no command");
+        if let Some(hint) = install_hint {
+            record["install_hint"] = json!(hint);
+        }
+        println!("{}", record);
+    }
+
+    fn no_file(&self, name: &str) {
+        println!("{}", base(name, "skipped"));
+    }
+
+    fn alternative(&self, name: &str, selected: &str) {
+        let mut record = base(name, "alternative");
+        record["selected"] = json!(selected);
+        println!("{}", record);
+    }
+
+    fn status(&self, name: &str, output: &Output) -> Result<()> {
+        let mut record = record(name, output);
+        if self.by_file {
+            self.collect(name, output.parsed());
+            record
+                .as_object_mut()
+                .map(|record| record.remove("diagnostics"));
+        }
+        println!("{}", record);
+        Ok(())
+    }
+
+    fn error(&self, name: &str, error: &MultilintError) {
+        let mut record = base(name, "error");
+        record["error"] = json!(format!("{:#}", error));
+        println!("{}", record);
+    }
+
+    fn warning(&self, name: &str, message: &str) {
+        println!("{}", json!({ "linter": name, "warning": message }));
+    }
+
+    fn finish(&self, summary: &Summary) -> Result<()> {
+        for record in self.file_records() {
+            println!("{}", record);
+        }
+        println!("{}", summary_record(summary));
+        Ok(())
+    }
+}
+
+// always the last record, telling a clean run from one where nothing ran
+fn summary_record(summary: &Summary) -> Value {
+    let mut record = json!(summary);
+    record["reason"] = json!(summary.reason());
+    json!({ "summary": record })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, record, summary_record, NdjsonFormat};
+    use crate::format::{OutputFormat, Summary};
+    use crate::{config::LinterConfig, linter::Linter};
+    use serde_json::json;
+    use std::path::Path;
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnostics() {
+        let root = tempdir().unwrap();
+        let run = |note_format: Vec<String>| {
+            let output = Linter::from_config(
+                LinterConfig {
+                    command: "printf".to_string(),
+                    options: vec!["a.rs:1:2: error\\nb.rs:3: note: here\\n".to_string()],
+                    parse_stream: crate::config::ParseStream::Stdout,
+                    note_format,
+                    ..Default::default()
+                },
+                &Default::default(),
+            )
+            .run(&root)
+            .unwrap()
+            .unwrap();
+            record("test", &output)
+        };
+
+        let flat = run(vec![]);
+        assert_eq!(flat["status"], "ok");
+        assert_eq!(
+            flat["diagnostics"],
+            json!([
+                { "file": "a.rs", "line": 1, "column": 2, "message": "error" },
+                { "file": "b.rs", "line": 3, "column": null, "message": "note: here", "severity": "note" },
+            ])
+        );
+
+        let nested = run(vec!["%f:%l: note: %m".to_string()]);
+        assert_eq!(
+            nested["diagnostics"],
+            json!([{
+                "file": "a.rs",
+                "line": 1,
+                "column": 2,
+                "message": "error",
+                "related": [{ "file": "b.rs", "line": 3, "column": null, "message": "here" }],
+            }])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn by_file() {
+        let root = tempdir().unwrap();
+        let run = |script: &str| {
+            Linter::from_config(
+                LinterConfig {
+                    command: "sh".to_string(),
+                    options: vec!["-c".to_string(), script.to_string()],
+                    ..Default::default()
+                },
+                &Default::default(),
+            )
+            .run(&root)
+            .unwrap()
+            .unwrap()
+        };
+        let a = run("echo 'src/a.rs:9:1: late'; echo 'b.rs:1: other'");
+        let b = run("echo 'a.rs:2:5: early'");
+
+        let format = NdjsonFormat::group_by_file();
+        format.start("first", Path::new(""));
+        format.status("first", &a).unwrap();
+        format.start("second", Path::new("src"));
+        format.status("second", &b).unwrap();
+        assert_eq!(
+            format.file_records(),
+            vec![
+                json!({ "file": "b.rs", "diagnostics": [
+                    { "linter": "first", "line": 1, "column": null, "message": "other" },
+                ] }),
+                json!({ "file": "src/a.rs", "diagnostics": [
+                    { "linter": "second", "line": 2, "column": 5, "message": "early" },
+                    { "linter": "first", "line": 9, "column": 1, "message": "late" },
+                ] }),
+            ]
+        );
+        assert!(format.file_records().is_empty());
+    }
+
+    #[test]
+    fn normalization() {
+        assert_eq!(normalize(Path::new(""), "./a.rs"), "a.rs");
+        assert_eq!(normalize(Path::new("sub"), "../src/./a.rs"), "src/a.rs");
+        assert_eq!(normalize(Path::new("sub"), "../../a.rs"), "../a.rs");
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            normalize(Path::new(""), &cwd.join("a.rs").to_string_lossy()),
+            "a.rs"
+        );
+    }
+
+    #[test]
+    fn summary() {
+        assert_eq!(
+            summary_record(&Default::default()),
+            json!({ "summary": { "configs": 0, "selected": 0, "ran": 0, "errors": 0, "reason": "no config found" } })
+        );
+        let summary = Summary {
+            configs: 1,
+            selected: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            summary_record(&summary)["summary"]["reason"],
+            "all linters skipped"
+        );
+        let summary = Summary {
+            errors: 1,
+            ..summary
+        };
+        assert_eq!(
+            summary_record(&summary)["summary"]["reason"],
+            "no linter could be run"
+        );
+        let summary = Summary { ran: 1, ..summary };
+        assert!(summary_record(&summary)["summary"]["reason"].is_null());
+    }
+}
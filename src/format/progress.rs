@@ -0,0 +1,143 @@
+use super::{OutputFormat, Summary};
+use crate::{error::MultilintError, linter::Output};
+use anyhow::Result;
+use colored::*;
+use std::{cell::Cell, path::Path};
+
+/// Prints progress and a summary to stderr, while another format writes to stdout
+pub struct ProgressFormat {
+    inner: Box<dyn OutputFormat>,
+    passed: Cell<usize>,
+    failed: Cell<usize>,
+    fixed: Cell<usize>,
+    skipped: Cell<usize>,
+    errors: Cell<usize>,
+}
+
+impl ProgressFormat {
+    pub fn new(inner: Box<dyn OutputFormat>) -> Self {
+        Self {
+            inner,
+            passed: Cell::new(0),
+            failed: Cell::new(0),
+            fixed: Cell::new(0),
+            skipped: Cell::new(0),
+            errors: Cell::new(0),
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} passed, {} failed, {} fixed, {} skipped, {} errors",
+            self.passed.get(),
+            self.failed.get(),
+            self.fixed.get(),
+            self.skipped.get(),
+            self.errors.get()
+        )
+    }
+}
+
+fn increment(counter: &Cell<usize>) {
+    counter.set(counter.get() + 1);
+}
+
+impl OutputFormat for ProgressFormat {
+    fn start(&self, name: &str, work_dir: &Path) {
+        eprint!("{} {} ... ", "Running".bold().green(), name);
+        self.inner.start(name, work_dir);
+    }
+
+    fn no_command(&self, name: &str, install_hint: Option<&str>) {
+        eprintln!("{}", "no command".yellow());
+        increment(&self.skipped);
+        self.inner.no_command(name, install_hint);
+    }
+
+    fn no_file(&self, name: &str) {
+        eprintln!("{}", "skipped".yellow());
+        increment(&self.skipped);
+        self.inner.no_file(name);
+    }
+
+    fn alternative(&self, name: &str, selected: &str) {
+        eprintln!(
+            "{}",
+            format!("skipped (alternative {} selected)", selected).yellow()
+        );
+        increment(&self.skipped);
+        self.inner.alternative(name, selected);
+    }
+
+    fn status(&self, name: &str, output: &Output) -> Result<()> {
+        if output.failed() {
+            eprintln!("{}", "failed".red());
+            increment(&self.failed);
+        } else if output.has_modifications() {
+            eprintln!("{}", "fixed".yellow());
+            increment(&self.fixed);
+        } else {
+            eprintln!("{}", "ok".green());
+            increment(&self.passed);
+        }
+        self.inner.status(name, output)
+    }
+
+    fn error(&self, name: &str, error: &MultilintError) {
+        eprintln!("{}", "error".red());
+        increment(&self.errors);
+        self.inner.error(name, error);
+    }
+
+    // shown by the inner format only, so that it is not printed twice
+    fn warning(&self, name: &str, message: &str) {
+        self.inner.warning(name, message);
+    }
+
+    fn finish(&self, summary: &Summary) -> Result<()> {
+        let result = self.inner.finish(summary);
+        let summary = self.summary();
+        if self.failed.get() + self.fixed.get() + self.errors.get() == 0 {
+            eprintln!("{}", summary.green());
+        } else {
+            eprintln!("{}", summary.red());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProgressFormat;
+    use crate::{
+        config::LinterConfig,
+        format::{NullFormat, OutputFormat},
+        linter::Linter,
+    };
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn summary() {
+        let root = tempdir().unwrap();
+        let output = Linter::from_config(
+            LinterConfig {
+                command: "true".to_string(),
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .run(&root)
+        .unwrap()
+        .unwrap();
+
+        let format = ProgressFormat::new(Box::<NullFormat>::default());
+        format.status("passed", &output).unwrap();
+        format.no_file("skipped");
+        format.finish(&Default::default()).unwrap();
+        assert_eq!(
+            format.summary(),
+            "1 passed, 0 failed, 0 fixed, 1 skipped, 0 errors"
+        );
+    }
+}
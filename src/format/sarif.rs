@@ -0,0 +1,238 @@
+use super::{OutputFormat, Summary};
+use crate::{error::MultilintError, linter::Output, parser::Parsed};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    fs,
+    io::{stdout, Write},
+    path::{Path, PathBuf},
+};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Writes a SARIF 2.1.0 log with a run per linter
+pub struct SarifFormat {
+    output: Option<PathBuf>,
+    pretty: bool,
+    runs: RefCell<Vec<Value>>,
+}
+
+impl SarifFormat {
+    /// Writes the log to `output`, or stdout if `None`, indented if `pretty`
+    pub fn new(output: Option<PathBuf>, pretty: bool) -> Self {
+        Self {
+            output,
+            pretty,
+            runs: RefCell::new(vec![]),
+        }
+    }
+
+    fn log(&self) -> Value {
+        json!({
+            "$schema": SCHEMA,
+            "version": "2.1.0",
+            "runs": *self.runs.borrow(),
+        })
+    }
+}
+
+fn run(name: &str, results: Vec<Value>, invocation: Value) -> Value {
+    json!({
+        "tool": { "driver": { "name": name } },
+        "invocations": [invocation],
+        "results": results,
+    })
+}
+
+fn location(file: &str, parsed: Option<&Parsed>) -> Value {
+    let mut location = json!({ "artifactLocation": { "uri": uri(file) } });
+    if let Some(line) = parsed.and_then(|p| p.line).filter(|&l| l > 0) {
+        let mut region = json!({ "startLine": line });
+        if let Some(column) = parsed.and_then(|p| p.column).filter(|&c| c > 0) {
+            region["startColumn"] = json!(column);
+        }
+        if let Some(end_line) = parsed.and_then(|p| p.end_line).filter(|&l| l >= line) {
+            region["endLine"] = json!(end_line);
+        }
+        if let Some(end_column) = parsed.and_then(|p| p.end_column).filter(|&c| c > 0) {
+            region["endColumn"] = json!(end_column);
+        }
+        location["region"] = region;
+    }
+    json!({ "physicalLocation": location })
+}
+
+fn result(parsed: &Parsed, default_level: &str) -> Value {
+    let level = match parsed.severity.as_deref() {
+        Some("error" | "fatal error") => "error",
+        Some("warning") => "warning",
+        Some("note" | "info" | "help") => "note",
+        _ => default_level,
+    };
+    let mut result = json!({
+        "level": level,
+        "message": { "text": parsed.message },
+        "locations": [location(&parsed.file, Some(parsed))],
+    });
+    if !parsed.related.is_empty() {
+        result["relatedLocations"] = parsed
+            .related
+            .iter()
+            .map(|r| {
+                let mut related = location(&r.file, Some(r));
+                related["message"] = json!({ "text": r.message });
+                related
+            })
+            .collect();
+    }
+    result
+}
+
+// relative references with forward slashes, escaping what cannot appear in a URI
+fn uri(path: &str) -> String {
+    let mut uri = String::with_capacity(path.len());
+    for c in path.replace('\\', "/").chars() {
+        match c {
+            '%' | ' ' | '#' | '?' | '"' | '<' | '>' => uri.push_str(&format!("%{:02X}", c as u32)),
+            _ => uri.push(c),
+        }
+    }
+    uri
+}
+
+impl OutputFormat for SarifFormat {
+    fn start(&self, _name: &str, _work_dir: &Path) {}
+
+    // skipped linters produce no run
+    fn no_command(&self, _name: &str, _install_hint: Option<&str>) {}
+
+    fn no_file(&self, _name: &str) {}
+
+    fn status(&self, name: &str, output: &Output) -> Result<()> {
+        let level = if output.failed() { "error" } else { "warning" };
+        let mut results: Vec<Value> = output.parsed().iter().map(|p| result(p, level)).collect();
+        for f in output.modified() {
+            results.push(json!({
+                "level": "error",
+                "message": { "text": format!("modified by {}", name) },
+                "locations": [location(&f.to_string_lossy(), None)],
+            }));
+        }
+        let mut invocation = json!({ "executionSuccessful": true });
+        if let Some(code) = output.exit_code() {
+            invocation["exitCode"] = json!(code);
+        }
+        self.runs.borrow_mut().push(run(name, results, invocation));
+        Ok(())
+    }
+
+    fn error(&self, name: &str, error: &MultilintError) {
+        let invocation = json!({
+            "executionSuccessful": false,
+            "toolExecutionNotifications": [
+                { "level": "error", "message": { "text": format!("{:#}", error) } },
+            ],
+        });
+        self.runs.borrow_mut().push(run(name, vec![], invocation));
+    }
+
+    // warnings do not fit in the log
+    fn finish(&self, _summary: &Summary) -> Result<()> {
+        let log = if self.pretty {
+            serde_json::to_string_pretty(&self.log())?
+        } else {
+            serde_json::to_string(&self.log())?
+        };
+        match &self.output {
+            Some(path) => fs::write(path, log)
+                .with_context(|| format!("Cannot write report \"{}\"", path.display()))?,
+            None => writeln!(stdout(), "{}", log)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{location, uri, SarifFormat};
+    use crate::{
+        config::LinterConfig, error::MultilintError, format::OutputFormat, linter::Linter,
+        parser::Parser,
+    };
+    use anyhow::anyhow;
+    use serde_json::json;
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[cfg(unix)]
+    #[test]
+    fn log() {
+        let root = tempdir().unwrap();
+        let output = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec![
+                    "-c".to_string(),
+                    "echo 'a b.rs:3:4: bad'; echo 'c.rs:5: note: here'; echo 'd.rs: x'; exit 1"
+                        .to_string(),
+                ],
+                error_format: vec!["%f:%l:%c: %m".to_string(), "%f: %m".to_string()],
+                note_format: vec!["%f:%l: note: %m".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .run(&root)
+        .unwrap()
+        .unwrap();
+
+        let format = SarifFormat::new(None, false);
+        format.status("lint", &output).unwrap();
+        format.no_file("skipped");
+        format.error("broken", &MultilintError::Other(anyhow!("boom")));
+        let log = format.log();
+        assert_eq!(log["version"], "2.1.0");
+        let runs = log["runs"].as_array().unwrap();
+        assert_eq!(runs.len(), 2);
+
+        assert_eq!(runs[0]["tool"]["driver"]["name"], "lint");
+        assert_eq!(runs[0]["invocations"][0]["exitCode"], 1);
+        let results = runs[0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[0]["message"]["text"], "bad");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "a%20b.rs");
+        assert_eq!(location["region"]["startLine"], 3);
+        assert_eq!(location["region"]["startColumn"], 4);
+        let related = &results[0]["relatedLocations"][0];
+        assert_eq!(related["physicalLocation"]["region"]["startLine"], 5);
+        assert_eq!(related["message"]["text"], "here");
+        assert!(results[1]["locations"][0]["physicalLocation"]
+            .get("region")
+            .is_none());
+
+        assert_eq!(runs[1]["invocations"][0]["executionSuccessful"], false);
+        assert!(runs[1]["results"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(uri("src/main.rs"), "src/main.rs");
+        assert_eq!(uri(r"src\a #1%.rs"), "src/a%20%231%25.rs");
+    }
+
+    #[test]
+    fn regions() {
+        let parser = Parser::new(["%f:%l:%c-%L:%C: %m", "%f:%l: %m"]).unwrap();
+        let parsed = parser.parse("a.rs:1:2-3:4: span\nb.rs:5: point\n");
+        let region =
+            |i: usize| location("x", Some(&parsed[i]))["physicalLocation"]["region"].clone();
+        assert_eq!(
+            region(0),
+            json!({ "startLine": 1, "startColumn": 2, "endLine": 3, "endColumn": 4 })
+        );
+        assert_eq!(region(1), json!({ "startLine": 5 }));
+    }
+}
@@ -0,0 +1,150 @@
+use super::OutputFormat;
+use crate::{error::MultilintError, linter::Output};
+use anyhow::{anyhow, Result};
+use log::debug;
+use std::{os::unix::net::UnixDatagram, path::Path, process};
+
+// sockets of the local syslog daemon, or journald through its compatibility socket
+const SOCKETS: &[&str] = &["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+// the "user" facility of RFC 5424
+const FACILITY: u8 = 1;
+
+// ordered from the most severe, as their codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+/// Sends the status and the parsed diagnostics of each linter to the local syslog daemon
+pub struct SyslogFormat {
+    socket: UnixDatagram,
+}
+
+impl SyslogFormat {
+    /// Connects to the first available socket of the syslog daemon
+    pub fn connect() -> Result<Self> {
+        SOCKETS
+            .iter()
+            .find_map(|path| Self::connect_to(path).ok())
+            .ok_or_else(|| anyhow!("Cannot connect to syslog at {}", SOCKETS.join(", ")))
+    }
+
+    pub fn connect_to(path: impl AsRef<Path>) -> Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self { socket })
+    }
+
+    fn send(&self, severity: Severity, message: &str) {
+        let priority = FACILITY * 8 + severity as u8;
+        let packet = format!("<{}>multilint[{}]: {}", priority, process::id(), message);
+        // losing a message should not stop the linters
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            debug!("cannot send to syslog: {}", e);
+        }
+    }
+}
+
+impl OutputFormat for SyslogFormat {
+    fn start(&self, _name: &str, _work_dir: &Path) {}
+
+    fn no_command(&self, name: &str, _install_hint: Option<&str>) {
+        self.send(Severity::Notice, &format!("{}: no command", name));
+    }
+
+    fn no_file(&self, name: &str) {
+        self.send(Severity::Info, &format!("{}: skipped", name));
+    }
+
+    fn alternative(&self, name: &str, selected: &str) {
+        let message = format!("{}: skipped (alternative {} selected)", name, selected);
+        self.send(Severity::Info, &message);
+    }
+
+    fn status(&self, name: &str, output: &Output) -> Result<()> {
+        let (severity, status) = if output.timed_out() {
+            (Severity::Error, "timed out")
+        } else if output.failed() {
+            (Severity::Error, "failed")
+        } else if output.has_modifications() {
+            (Severity::Notice, "fixed")
+        } else {
+            (Severity::Info, "ok")
+        };
+        self.send(severity, &format!("{}: {}", name, status));
+        // diagnostics of a passing linter are only warnings
+        let severity = severity.min(Severity::Warning);
+        for p in output.parsed() {
+            self.send(
+                severity,
+                &format!("{}: {}: {}", name, p.location(), p.message),
+            );
+        }
+        for f in output.modified() {
+            self.send(
+                Severity::Notice,
+                &format!("{}: {}: modified", name, f.display()),
+            );
+        }
+        Ok(())
+    }
+
+    fn error(&self, name: &str, error: &MultilintError) {
+        self.send(Severity::Error, &format!("{}: {:#}", name, error));
+    }
+
+    fn warning(&self, name: &str, message: &str) {
+        self.send(
+            Severity::Warning,
+            &format!("{}: warning: {}", name, message),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SyslogFormat;
+    use crate::{config::LinterConfig, format::OutputFormat, linter::Linter};
+    use std::{os::unix::net::UnixDatagram, time::Duration};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn messages() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("log");
+        let server = UnixDatagram::bind(&path).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let receive = || {
+            let mut buf = [0; 1024];
+            let n = server.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        };
+
+        let output = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec!["-c".to_string(), "echo a.rs:1:2: bad; exit 1".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .run(&dir)
+        .unwrap()
+        .unwrap();
+        let format = SyslogFormat::connect_to(&path).unwrap();
+        format.status("lint", &output).unwrap();
+        format.no_file("other");
+
+        // user.err, user.err and user.info
+        assert!(receive().starts_with("<11>multilint["));
+        assert!(receive().ends_with("]: lint: a.rs:1:2: bad"));
+        assert!(receive().starts_with("<14>"));
+    }
+}
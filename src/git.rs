@@ -0,0 +1,321 @@
+use anyhow::{Context, Result};
+use log::debug;
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Lists files that git reports as untracked or modified, as canonical paths
+///
+/// Returns `None` if `root` is not inside a git work tree.
+pub fn uncommitted(root: impl AsRef<Path>) -> Option<HashSet<PathBuf>> {
+    let root = root.as_ref();
+    let toplevel = toplevel(root)?;
+    let status = git(
+        root,
+        &["status", "--porcelain", "-z", "--untracked-files=all"],
+    )?;
+    Some(
+        parse_status(&status)
+            .into_iter()
+            .map(|path| toplevel.join(path))
+            .collect(),
+    )
+}
+
+/// Lists files that differ from `rev` in the work tree, or are untracked, as canonical paths
+///
+/// Returns `None` if `root` is not inside a git work tree or `rev` is not a valid revision.
+pub fn changed(root: impl AsRef<Path>, rev: &str) -> Option<HashSet<PathBuf>> {
+    let root = root.as_ref();
+    let toplevel = toplevel(root)?;
+    let diff = git(root, &["diff", "--name-only", "-z", rev, "--"])?;
+    let untracked = git(
+        root,
+        &[
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+            "-z",
+        ],
+    )?;
+    Some(
+        diff.split(|&b| b == 0)
+            .chain(untracked.split(|&b| b == 0))
+            .filter(|path| !path.is_empty())
+            .map(|path| toplevel.join(to_path(path)))
+            .collect(),
+    )
+}
+
+/// Lists tracked files whose changes in the work tree are not all staged, as canonical paths
+///
+/// Returns `None` if `root` is not inside a git work tree.
+pub fn unstaged(root: impl AsRef<Path>) -> Option<HashSet<PathBuf>> {
+    let root = root.as_ref();
+    let toplevel = toplevel(root)?;
+    let diff = git(root, &["diff", "--name-only", "-z"])?;
+    Some(
+        diff.split(|&b| b == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| toplevel.join(to_path(path)))
+            .collect(),
+    )
+}
+
+/// Stages again those of the files that were staged, returning them as canonical paths
+///
+/// Returns `None` if `root` is not inside a git work tree, and fails if git does.
+pub fn restage(root: impl AsRef<Path>, files: &[PathBuf]) -> Result<Option<Vec<PathBuf>>> {
+    let root = root.as_ref();
+    let toplevel = match toplevel(root) {
+        Some(toplevel) => toplevel,
+        None => return Ok(None),
+    };
+    let staged: HashSet<PathBuf> = git(root, &["diff", "--cached", "--name-only", "-z"])
+        .context("Cannot list the staged files with git")?
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| toplevel.join(to_path(path)))
+        .collect();
+    let mut restaged: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|file| fs::canonicalize(file).ok())
+        .filter(|file| staged.contains(file))
+        .collect();
+    restaged.sort();
+    restaged.dedup();
+    if !restaged.is_empty() {
+        let mut args = vec![OsStr::new("add"), OsStr::new("--")];
+        args.extend(restaged.iter().map(|file| file.as_os_str()));
+        git(root, &args).context("Cannot stage the fixed files again with git add")?;
+    }
+    Ok(Some(restaged))
+}
+
+pub(crate) fn toplevel(root: &Path) -> Option<PathBuf> {
+    let toplevel = git(root, &["rev-parse", "--show-toplevel"])?;
+    fs::canonicalize(to_path(toplevel.trim_ascii_end())).ok()
+}
+
+/// Whether the directory is a submodule or another repository nested in the walked tree
+///
+/// Submodules have a `.git` file pointing into the `modules` of the parent, while older ones and
+/// nested clones have a `.git` directory. Linked worktrees, whose `.git` file points into
+/// `worktrees`, belong to the same repository and are not excluded. Symlinks are followed.
+pub fn is_submodule(dir: &Path) -> bool {
+    let dot_git = dir.join(".git");
+    let metadata = match fs::metadata(&dot_git) {
+        Ok(metadata) => metadata,
+        Err(_) => return false,
+    };
+    if metadata.is_dir() {
+        return dot_git.join("HEAD").is_file();
+    }
+    let text = match fs::read_to_string(&dot_git) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    match text.trim_end().strip_prefix("gitdir:") {
+        Some(gitdir) => {
+            let gitdir = Path::new(gitdir.trim());
+            let kind = gitdir.parent().and_then(Path::file_name);
+            debug!("{}: gitdir {}", dir.display(), gitdir.display());
+            kind != Some(OsStr::new("worktrees"))
+        }
+        None => false,
+    }
+}
+
+fn git(root: &Path, args: &[impl AsRef<OsStr>]) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        let args: Vec<_> = args.iter().map(|a| a.as_ref().to_string_lossy()).collect();
+        debug!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+        return None;
+    }
+    Some(output.stdout)
+}
+
+/// Parses `git status --porcelain -z` into paths relative to the top level
+fn parse_status(status: &[u8]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let mut records = status.split(|&b| b == 0).filter(|r| !r.is_empty());
+    while let Some(record) = records.next() {
+        let (code, path) = match (record.get(..2), record.get(3..)) {
+            (Some(code), Some(path)) => (code, path),
+            _ => continue,
+        };
+        // renames and copies are followed by the original path
+        if code.contains(&b'R') || code.contains(&b'C') {
+            records.next();
+        }
+        paths.push(to_path(path));
+    }
+    paths
+}
+
+#[cfg(unix)]
+fn to_path(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changed, is_submodule, parse_status, restage, uncommitted, unstaged};
+    use std::{collections::HashSet, fs, path::PathBuf, process::Command};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn status() {
+        let status = b" M src/lib.rs\0R  new.rs\0old.rs\0?? a b.rs\0";
+        assert_eq!(
+            parse_status(status),
+            vec![
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("new.rs"),
+                PathBuf::from("a b.rs")
+            ]
+        );
+        assert!(parse_status(b"").is_empty());
+    }
+
+    #[test]
+    fn outside_repository() {
+        let root = tempdir().unwrap();
+        assert!(uncommitted(&root).is_none());
+    }
+
+    #[test]
+    fn changed_files() {
+        let root = tempdir().unwrap();
+        assert!(changed(&root, "HEAD").is_none());
+
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .current_dir(&root)
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{:?}", output);
+        };
+        git(&["init"]);
+        git(&["config", "user.name", "test"]);
+        git(&["config", "user.email", "test"]);
+        fs::write(root.path().join("a.rs"), "").unwrap();
+        fs::write(root.path().join("b.rs"), "").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-m", "first"]);
+        fs::write(root.path().join("b.rs"), "fn b() {}").unwrap();
+        git(&["commit", "-am", "second"]);
+        assert_eq!(changed(&root, "HEAD"), Some(HashSet::new()));
+
+        fs::write(root.path().join("a.rs"), "fn a() {}").unwrap();
+        fs::write(root.path().join("c.rs"), "").unwrap();
+        let top = fs::canonicalize(&root).unwrap();
+        let expected = |names: &[&str]| Some(names.iter().map(|name| top.join(name)).collect());
+        assert_eq!(changed(&root, "HEAD"), expected(&["a.rs", "c.rs"]));
+        assert_eq!(
+            changed(&root, "HEAD~1"),
+            expected(&["a.rs", "b.rs", "c.rs"])
+        );
+        assert!(changed(&root, "nonexistent").is_none());
+    }
+
+    #[test]
+    fn restage_files() {
+        let root = tempdir().unwrap();
+        assert!(restage(&root, &[]).unwrap().is_none());
+        assert!(unstaged(&root).is_none());
+
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .current_dir(&root)
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{:?}", output);
+            String::from_utf8(output.stdout).unwrap()
+        };
+        git(&["init"]);
+        fs::write(root.path().join("a.rs"), "fn a(){}").unwrap();
+        fs::write(root.path().join("b.rs"), "fn b(){}").unwrap();
+        git(&["add", "a.rs"]);
+        // formatted after staging
+        fs::write(root.path().join("a.rs"), "fn a() {}").unwrap();
+        fs::write(root.path().join("b.rs"), "fn b() {}").unwrap();
+
+        let files = [root.path().join("a.rs"), root.path().join("b.rs")];
+        let top = fs::canonicalize(&root).unwrap();
+        assert_eq!(unstaged(&root), Some(HashSet::from([top.join("a.rs")])));
+        assert_eq!(
+            restage(&root, &files).unwrap(),
+            Some(vec![top.join("a.rs")])
+        );
+        assert_eq!(unstaged(&root), Some(HashSet::new()));
+        assert_eq!(git(&["show", ":a.rs"]), "fn a() {}");
+        assert_eq!(git(&["status", "--porcelain"]), "A  a.rs\n?? b.rs\n");
+    }
+
+    #[test]
+    fn submodules() {
+        let root = tempdir().unwrap();
+        let dir = |name: &str, dot_git: Option<&str>| {
+            let dir = root.path().join(name);
+            fs::create_dir(&dir).unwrap();
+            if let Some(text) = dot_git {
+                fs::write(dir.join(".git"), text).unwrap();
+            }
+            dir
+        };
+
+        assert!(!is_submodule(&dir("plain", None)));
+        assert!(is_submodule(&dir(
+            "sub",
+            Some("gitdir: ../.git/modules/sub\n")
+        )));
+        assert!(!is_submodule(&dir(
+            "worktree",
+            Some("gitdir: /repo/.git/worktrees/worktree\n")
+        )));
+        assert!(!is_submodule(&dir("other", Some("not a git file"))));
+
+        let clone = dir("clone", None);
+        fs::create_dir(clone.join(".git")).unwrap();
+        assert!(!is_submodule(&clone));
+        fs::write(clone.join(".git").join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert!(is_submodule(&clone));
+
+        #[cfg(unix)]
+        {
+            let linked = dir("linked", None);
+            std::os::unix::fs::symlink(root.path().join("sub").join(".git"), linked.join(".git"))
+                .unwrap();
+            assert!(is_submodule(&linked));
+            let dangling = dir("dangling", None);
+            std::os::unix::fs::symlink(root.path().join("missing"), dangling.join(".git")).unwrap();
+            assert!(!is_submodule(&dangling));
+        }
+    }
+}
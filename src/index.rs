@@ -0,0 +1,431 @@
+use crate::{config::GlobalConfig, git};
+use anyhow::{Context, Result};
+use ignore::{overrides::Override, overrides::OverrideBuilder, WalkBuilder};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Directory holding files cached between runs, which is never walked
+pub const CACHE_DIR: &str = ".multilint-cache";
+
+const INDEX_FILE: &str = "index.json";
+
+// files whose changes affect which entries are ignored
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// Ignore files applied by walks, from the global config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IgnoreRules {
+    /// Apply `.gitignore`, `.git/info/exclude` and the global gitignore of git
+    pub gitignore: bool,
+
+    /// Names of additional ignore files
+    pub files: Vec<String>,
+}
+
+impl Default for IgnoreRules {
+    fn default() -> Self {
+        Self {
+            gitignore: true,
+            files: vec![],
+        }
+    }
+}
+
+impl IgnoreRules {
+    pub fn from_config(global: &GlobalConfig) -> Self {
+        Self {
+            gitignore: global.respect_gitignore,
+            files: global.ignore_files.clone(),
+        }
+    }
+
+    /// Starts a walk of the directory with the rules, never entering `.git` or the cache
+    pub fn walk(&self, start: &Path) -> Result<WalkBuilder> {
+        let mut walk = WalkBuilder::new(start);
+        walk.hidden(false)
+            .git_ignore(self.gitignore)
+            .git_global(self.gitignore)
+            .git_exclude(self.gitignore)
+            .overrides(
+                OverrideBuilder::new(start)
+                    .add("!.git/")?
+                    .add(&format!("!{}/", CACHE_DIR))?
+                    .build()?,
+            );
+        for file in &self.files {
+            walk.add_custom_ignore_filename(file);
+        }
+        Ok(walk)
+    }
+}
+
+/// On-disk snapshot of the walk, refreshed by directory mtimes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Index {
+    /// Directories relative to the root, encoded by `encode`, with the root as an empty path
+    dirs: BTreeMap<String, Dir>,
+
+    /// Rules the directories were scanned with, which are scanned again once they change
+    #[serde(default)]
+    rules: IgnoreRules,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Dir {
+    stamp: Stamp,
+    submodule: bool,
+    /// File names encoded by `encode`
+    files: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Stamp {
+    modified: SystemTime,
+    ignores: Vec<Option<SystemTime>>,
+}
+
+impl Stamp {
+    fn new(dir: &Path, rules: &IgnoreRules) -> Option<Self> {
+        let modified = fs::metadata(dir).and_then(|m| m.modified()).ok()?;
+        let ignores = IGNORE_FILES
+            .iter()
+            .copied()
+            .chain(rules.files.iter().map(String::as_str))
+            .map(|f| fs::metadata(dir.join(f)).and_then(|m| m.modified()).ok())
+            .collect();
+        Some(Self { modified, ignores })
+    }
+}
+
+impl Index {
+    /// Loads the index under the root and refreshes stale directories
+    ///
+    /// The whole tree is walked again if `reindex` is set, the index cannot be read or it was
+    /// built with other ignore rules.
+    pub fn load(root: impl AsRef<Path>, reindex: bool, rules: &IgnoreRules) -> Result<Self> {
+        let root = root.as_ref();
+        let path = root.join(CACHE_DIR).join(INDEX_FILE);
+        let mut index = if reindex || !path.exists() {
+            Self::default()
+        } else {
+            match fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice::<Self>(&data)?))
+            {
+                Ok(index) if index.rules == *rules => index,
+                Ok(_) => {
+                    debug!("rebuilding index {}: ignore rules changed", path.display());
+                    Self::default()
+                }
+                Err(e) => {
+                    warn!("rebuilding index {}: {:#}", path.display(), e);
+                    Self::default()
+                }
+            }
+        };
+        index.rules = rules.clone();
+        index.refresh(root)?;
+        if let Err(e) = index.save(root) {
+            warn!("{:#}", e);
+        }
+        Ok(index)
+    }
+
+    fn save(&self, root: &Path) -> Result<()> {
+        let dir = root.join(CACHE_DIR);
+        let path = dir.join(INDEX_FILE);
+        fs::create_dir_all(&dir)
+            .and_then(|_| fs::write(&path, serde_json::to_vec(self)?))
+            .with_context(|| format!("Cannot write index \"{}\"", path.display()))
+    }
+
+    fn refresh(&mut self, root: &Path) -> Result<()> {
+        if self.dirs.is_empty() {
+            return self.scan(root, Path::new(""));
+        }
+
+        let stale: Vec<PathBuf> = self
+            .dirs
+            .iter()
+            .map(|(dir, entry)| (decode(dir), entry))
+            .filter(|(dir, entry)| {
+                Stamp::new(&root.join(dir), &self.rules).as_ref() != Some(&entry.stamp)
+            })
+            .map(|(dir, _)| dir)
+            .collect();
+        let stale_set: HashSet<&Path> = stale.iter().map(PathBuf::as_path).collect();
+        for dir in &stale {
+            // a stale ancestor is scanned including this directory
+            if dir.ancestors().skip(1).any(|a| stale_set.contains(a)) {
+                continue;
+            }
+            debug!("reindexing {}", dir.display());
+            self.dirs.retain(|d, _| !decode(d).starts_with(dir));
+            if root.join(dir).is_dir() {
+                self.scan(root, dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn scan(&mut self, root: &Path, dir: &Path) -> Result<()> {
+        let start = root.join(dir);
+        for entry in self.rules.walk(&start)?.build() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("traversal error: {}", err);
+                    continue;
+                }
+            };
+            let file_type = match entry.file_type() {
+                Some(file_type) => file_type,
+                None => continue,
+            };
+            let path = dir.join(entry.path().strip_prefix(&start)?);
+            if file_type.is_dir() {
+                if let Some(stamp) = Stamp::new(entry.path(), &self.rules) {
+                    self.dirs.insert(
+                        encode(&path),
+                        Dir {
+                            stamp,
+                            submodule: git::is_submodule(entry.path()),
+                            files: vec![],
+                        },
+                    );
+                }
+            } else if !file_type.is_symlink() {
+                let parent = path.parent().unwrap_or_else(|| Path::new(""));
+                let dir = self.dirs.get_mut(&encode(parent));
+                if let (Some(dir), Some(name)) = (dir, path.file_name()) {
+                    dir.files.push(encode(Path::new(name)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists indexed files like a walk, skipping directories matched by `pruned`
+    pub fn files(
+        &self,
+        root: impl AsRef<Path>,
+        pruned: Option<&Override>,
+        exclude_submodules: bool,
+    ) -> Vec<PathBuf> {
+        let root = root.as_ref();
+        let mut skipped = HashSet::new();
+        let mut files = Vec::new();
+        for (dir, entry) in &self.dirs {
+            let dir = decode(dir);
+            if dir.ancestors().any(|a| skipped.contains(a)) {
+                continue;
+            }
+            let path = root.join(&dir);
+            let path = path.strip_prefix(".").unwrap_or(&path);
+            if !dir.as_os_str().is_empty() {
+                let pruned = pruned.is_some_and(|p| p.matched(path, true).is_whitelist());
+                if pruned || (exclude_submodules && entry.submodule) {
+                    debug!("pruning {}", path.display());
+                    skipped.insert(dir.clone());
+                    continue;
+                }
+            }
+            files.extend(entry.files.iter().map(|f| path.join(decode(f))));
+        }
+        files
+    }
+}
+
+/// Encodes a path as a string, escaping `%` and bytes that are not UTF-8 as `%XX`
+#[cfg(unix)]
+fn encode(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '%' => encoded.push_str("%25"),
+                _ => encoded.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+#[cfg(unix)]
+fn decode(encoded: &str) -> PathBuf {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(decoded))
+}
+
+// paths on other platforms are Unicode in practice
+#[cfg(not(unix))]
+fn encode(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+#[cfg(not(unix))]
+fn decode(encoded: &str) -> PathBuf {
+    PathBuf::from(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IgnoreRules, Index};
+    use ignore::overrides::OverrideBuilder;
+    use std::{
+        fs::{self, create_dir_all, File},
+        path::Path,
+    };
+    use tempfile::tempdir;
+    use test_log::test;
+
+    fn files(index: &Index, root: &Path) -> Vec<String> {
+        let mut files: Vec<String> = index
+            .files(root, None, true)
+            .iter()
+            .map(|f| f.strip_prefix(root).unwrap().display().to_string())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn refresh() {
+        let root = tempdir().unwrap();
+        let src = root.path().join("src");
+        create_dir_all(&src).unwrap();
+        File::create(root.path().join("main.rs")).unwrap();
+        File::create(src.join("lib.rs")).unwrap();
+
+        let index = Index::load(&root, false, &Default::default()).unwrap();
+        assert!(root
+            .path()
+            .join(".multilint-cache")
+            .join("index.json")
+            .is_file());
+        assert_eq!(
+            files(&index, root.path()),
+            vec![
+                "main.rs".to_string(),
+                Path::new("src").join("lib.rs").display().to_string()
+            ]
+        );
+
+        fs::remove_file(src.join("lib.rs")).unwrap();
+        File::create(src.join("new.rs")).unwrap();
+        let index = Index::load(&root, false, &Default::default()).unwrap();
+        assert_eq!(
+            files(&index, root.path()),
+            vec![
+                "main.rs".to_string(),
+                Path::new("src").join("new.rs").display().to_string()
+            ]
+        );
+
+        let index = Index::load(&root, true, &Default::default()).unwrap();
+        assert_eq!(files(&index, root.path()).len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8() {
+        use super::{decode, encode};
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let name = Path::new(OsStr::from_bytes(b"a\xff%b.rs"));
+        assert_eq!(encode(name), "a%FF%25b.rs");
+        assert_eq!(decode(&encode(name)), name);
+
+        let root = tempdir().unwrap();
+        File::create(root.path().join(name)).unwrap();
+        Index::load(&root, false, &Default::default()).unwrap();
+        let index = Index::load(&root, false, &Default::default()).unwrap();
+        assert_eq!(index.files(&root, None, true), vec![root.path().join(name)]);
+    }
+
+    #[test]
+    fn ignore_rules() {
+        let root = tempdir().unwrap();
+        create_dir_all(root.path().join(".git")).unwrap();
+        create_dir_all(root.path().join("target")).unwrap();
+        fs::write(root.path().join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.path().join(".lintignore"), "gen.rs\n").unwrap();
+        File::create(root.path().join("target").join("a.o")).unwrap();
+        File::create(root.path().join("gen.rs")).unwrap();
+        File::create(root.path().join("main.rs")).unwrap();
+
+        let index = Index::load(&root, false, &Default::default()).unwrap();
+        assert_eq!(
+            files(&index, root.path()),
+            vec![".gitignore", ".lintignore", "gen.rs", "main.rs"]
+        );
+
+        // other rules rebuild the index
+        let rules = IgnoreRules {
+            gitignore: false,
+            files: vec![".lintignore".to_string()],
+        };
+        let index = Index::load(&root, false, &rules).unwrap();
+        assert_eq!(
+            files(&index, root.path()),
+            vec![
+                ".gitignore".to_string(),
+                ".lintignore".to_string(),
+                "main.rs".to_string(),
+                Path::new("target").join("a.o").display().to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn prune() {
+        let root = tempdir().unwrap();
+        let gen = root.path().join("gen");
+        let sub = root.path().join("sub");
+        create_dir_all(&gen).unwrap();
+        create_dir_all(&sub).unwrap();
+        File::create(gen.join("a.rs")).unwrap();
+        fs::write(sub.join(".git"), "gitdir: ../.git/modules/sub").unwrap();
+        File::create(sub.join("b.rs")).unwrap();
+
+        let index = Index::load(&root, false, &Default::default()).unwrap();
+        let pruned = OverrideBuilder::new(&root)
+            .add("gen")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(index.files(&root, Some(&pruned), true).is_empty());
+        assert_eq!(index.files(&root, Some(&pruned), false).len(), 2);
+    }
+}
@@ -0,0 +1,22 @@
+use crate::{sandbox, xargs};
+use log::warn;
+use std::{process, sync::Once};
+
+static INSTALL: Once = Once::new();
+
+/// Installs the Ctrl-C handler removing the sandboxes and terminating the runs with a timeout
+///
+/// It is installed on the first sandbox or run with a timeout, as other runs need no cleanup and
+/// are interrupted with multilint itself.
+pub(crate) fn install() {
+    INSTALL.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            sandbox::cleanup();
+            xargs::terminate_all();
+            process::exit(130);
+        });
+        if let Err(e) = result {
+            warn!("Cannot handle Ctrl-C: {}", e);
+        }
+    });
+}
@@ -1,5 +1,17 @@
+pub mod cache;
+pub mod check;
 pub mod config;
+pub mod doctor;
 pub mod driver;
+pub mod error;
 pub mod format;
+pub mod git;
+pub mod index;
+mod interrupt;
 pub mod linter;
+pub mod parser;
+pub mod patch;
+pub mod sandbox;
 pub mod xargs;
+
+pub use error::MultilintError;
@@ -1,28 +1,151 @@
 use crate::{
-    config::{GlobalConfig, LinterConfig},
-    xargs::Xargs,
+    cache::{self, Record, ResultCache},
+    config::{
+        GlobalConfig, HashAlgorithm, InputEncoding, JsonFields, LinterConfig, OutputKind,
+        ParseStream,
+    },
+    error::{MultilintError, Result},
+    git,
+    index::{IgnoreRules, Index},
+    parser::{JsonParser, Parsed, Parser, StreamParser, GNU_FORMATS},
+    sandbox::Sandbox,
+    xargs::{self, Semaphore, Xargs},
+};
+use anyhow::{ensure, Context};
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    DirEntry, Match,
 };
-use anyhow::{ensure, Result};
-use digest;
-use ignore::{overrides::OverrideBuilder, DirEntry, Match, WalkBuilder};
 use log::{debug, warn};
+use regex::bytes::Regex;
+use serde::Serialize;
+use sha1::Sha1;
 use sha2::{Digest, Sha256};
 use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::{BTreeMap, HashSet},
+    env,
+    ffi::OsString,
     fs,
-    path::{Path, PathBuf},
+    io::Write,
+    path::{Component, Path, PathBuf},
     process,
-    time::SystemTime,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
+use tempfile::NamedTempFile;
+use xxhash_rust::xxh3::xxh3_128;
+
+const DEFAULT_MAX_PASSES: usize = 5;
+
+// runs the command line of `shell`, followed by the files
+#[cfg(not(windows))]
+const SHELL: [&str; 2] = ["sh", "-c"];
+#[cfg(windows)]
+const SHELL: [&str; 2] = ["cmd", "/C"];
+const DEFAULT_KILL_GRACE: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone)]
 pub struct Output {
     process: process::Output,
     modified: Vec<PathBuf>,
+    modified_outside: Vec<PathBuf>,
+    strict: bool,
+    fix: bool,
+    duration: Duration,
+    stats: Stats,
+    parsed: Vec<Parsed>,
+    timed_out: bool,
+    unstable: bool,
+    cached: bool,
+    failure: Option<process::ExitStatus>,
+    originals: Vec<(PathBuf, Vec<u8>)>,
+}
+
+/// Counts describing a linter run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    /// Files matching the includes and excludes
+    pub matched: usize,
+
+    /// Files passed to the linter, after the scope and size limit
+    pub files: usize,
+
+    /// Number of times the linter command was run
+    pub batches: usize,
+
+    /// Files modified by the linter
+    pub modified: usize,
+
+    /// Number of runs over the files, more than one with `retry_until_stable`
+    pub passes: usize,
 }
 
 impl Output {
+    /// Whether the linter passed, where modifications of the inputs pass in fix mode
     pub fn success(&self) -> bool {
-        self.process.status.success() && self.modified.is_empty()
+        !self.failed() && (self.fix || !self.has_modifications())
+    }
+
+    /// Whether the linter failed, by exiting with failure or modifying files outside its inputs
+    /// in strict mode
+    pub fn failed(&self) -> bool {
+        self.process_failed() || self.integrity_violated()
+    }
+
+    /// Whether the linter process exited with failure
+    pub fn process_failed(&self) -> bool {
+        !self.process.status.success()
+    }
+
+    /// Whether the linter modified files outside its inputs in strict mode
+    pub fn integrity_violated(&self) -> bool {
+        self.strict && !self.modified_outside.is_empty()
+    }
+
+    /// Exit code of the linter process, or `None` if it was killed by a signal
+    pub fn exit_code(&self) -> Option<i32> {
+        self.process.status.code()
+    }
+
+    /// Exit code of the failing run of the linter, which `exit_code` reports as 123 if the files
+    /// took several runs
+    ///
+    /// It is the highest code among the runs, or `None` if none failed or one was killed by a signal.
+    pub fn failure_code(&self) -> Option<i32> {
+        self.failure.and_then(|status| status.code())
+    }
+
+    /// Signal that killed a run of the linter, such as 11 for a segmentation fault (unix only)
+    pub fn signal(&self) -> Option<i32> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            self.failure.and_then(|status| status.signal())
+        }
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// Whether the linter process was killed for exceeding its timeout
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    /// Whether the linter still modified files in its last pass with `retry_until_stable`
+    pub fn unstable(&self) -> bool {
+        self.unstable
+    }
+
+    /// Whether the result was replayed from the cache instead of running the linter
+    pub fn cached(&self) -> bool {
+        self.cached
+    }
+
+    /// Whether the linter modified any input files
+    pub fn has_modifications(&self) -> bool {
+        !self.modified.is_empty()
     }
 
     pub fn stdout(&self) -> &[u8] {
@@ -36,33 +159,71 @@ impl Output {
     pub fn modified(&self) -> &[PathBuf] {
         &self.modified
     }
+
+    /// Contents of the modified files before the linter ran, kept by `snapshot`
+    pub fn originals(&self) -> &[(PathBuf, Vec<u8>)] {
+        &self.originals
+    }
+
+    /// Files modified by the linter although they were not passed to it
+    pub fn modified_outside(&self) -> &[PathBuf] {
+        &self.modified_outside
+    }
+
+    /// Wall-clock time spent running the linter
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Diagnostics parsed from stderr, then stdout
+    pub fn parsed(&self) -> &[Parsed] {
+        &self.parsed
+    }
 }
 
 struct Entry {
     path: PathBuf,
     modified: SystemTime,
-    hash: Option<digest::Output<Sha256>>,
+    hash: Option<(HashAlgorithm, InputEncoding, Vec<u8>)>,
+    backup: Option<PathBuf>,
 }
 
 impl Entry {
-    fn new(path: impl AsRef<Path>, use_hash: bool) -> Result<Entry> {
+    /// Records the state of the file, copying it to `backup` if any to read it back if modified
+    fn new(
+        path: impl AsRef<Path>,
+        algorithm: Option<HashAlgorithm>,
+        encoding: InputEncoding,
+        backup: Option<PathBuf>,
+    ) -> anyhow::Result<Entry> {
         let metadata = fs::metadata(&path)?;
-        let hash = if use_hash {
-            Some(Sha256::digest(fs::read(&path)?))
-        } else {
-            None
+        if let Some(backup) = &backup {
+            fs::copy(&path, backup)?;
+        }
+        let hash = match algorithm {
+            Some(algorithm) => Some((
+                algorithm,
+                encoding,
+                hash(algorithm, encoding, &fs::read(&path)?),
+            )),
+            None => None,
         };
         Ok(Entry {
             path: path.as_ref().to_owned(),
             modified: metadata.modified()?,
             hash,
+            backup,
         })
     }
 
-    fn is_same(&self) -> Result<bool> {
-        if let Some(hash) = &self.hash {
-            let new_hash = &Sha256::digest(fs::read(&self.path)?);
-            return Ok(hash == new_hash);
+    fn is_same(&self) -> anyhow::Result<bool> {
+        if let Some((algorithm, encoding, old_hash)) = &self.hash {
+            let new_hash = hash(*algorithm, *encoding, &fs::read(&self.path)?);
+            return Ok(old_hash == &new_hash);
         }
 
         let metadata = fs::metadata(&self.path)?;
@@ -71,30 +232,312 @@ impl Entry {
     }
 }
 
+fn hash(algorithm: HashAlgorithm, encoding: InputEncoding, contents: &[u8]) -> Vec<u8> {
+    let contents = match encoding {
+        InputEncoding::Raw => Cow::Borrowed(contents),
+        InputEncoding::Auto => decode(contents),
+    };
+    match algorithm {
+        HashAlgorithm::Sha256 => Sha256::digest(contents).to_vec(),
+        HashAlgorithm::Sha1 => Sha1::digest(contents).to_vec(),
+        HashAlgorithm::Blake3 => blake3::hash(&contents).as_bytes().to_vec(),
+        HashAlgorithm::Xxh3 => xxh3_128(&contents).to_le_bytes().to_vec(),
+    }
+}
+
+/// Converts contents to UTF-8 if it starts with a byte order mark
+pub(crate) fn decode(bytes: &[u8]) -> Cow<'_, [u8]> {
+    fn utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Cow<'_, [u8]> {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|c| from_bytes([c[0], c[1]]))
+            .collect();
+        Cow::Owned(String::from_utf16_lossy(&units).into_bytes())
+    }
+
+    match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => Cow::Borrowed(rest),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => Cow::Borrowed(bytes),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Linter {
     command: String,
+    digest: String,
     options: Vec<String>,
+    env: BTreeMap<String, String>,
     includes: Vec<String>,
     excludes: Vec<String>,
     work_dir: PathBuf,
+    create_work_dir: bool,
     exclude_submodules: bool,
+    ignore_rules: IgnoreRules,
     single_file: bool,
-    check_hash: bool,
+    max_procs: Option<usize>,
+    argfile: bool,
+    sandbox: bool,
+    pty: bool,
+    max_passes: usize,
+    retries: u32,
+    retry_on_exit_codes: Vec<i32>,
+    timeout: Option<Duration>,
+    kill_signal: Option<String>,
+    kill_grace: Duration,
+    stream: bool,
+    prefix: Option<String>,
+    snapshot: bool,
+    uncommitted_only: bool,
+    hash: Option<HashAlgorithm>,
+    input_encoding: InputEncoding,
+    max_file_size: Option<u64>,
+    post_run: Vec<String>,
+    stdout_formats: Vec<String>,
+    stderr_formats: Vec<String>,
+    note_formats: Vec<String>,
+    output: OutputKind,
+    json_fields: JsonFields,
+    output_filter: Vec<String>,
+    scope: Vec<String>,
+    check_integrity: bool,
+    strict: bool,
+    fix: bool,
+    semaphore: Option<Arc<Semaphore>>,
+    given: Option<Arc<Vec<PathBuf>>>,
+    index: Option<Arc<Index>>,
+    max_walk_time: Option<Duration>,
+    last_walk: Cell<Option<(Duration, usize)>>,
+    excluded: Cell<usize>,
+    newer_than: Option<SystemTime>,
+    changed_since: Option<String>,
+    cache: bool,
 }
 
 impl Linter {
     pub fn from_config(config: LinterConfig, global: &GlobalConfig) -> Self {
+        let (command, options) = match &config.shell {
+            // sh takes the files as the arguments after its name, $0
+            Some(shell) if cfg!(not(windows)) => (
+                SHELL[0].to_string(),
+                vec![
+                    SHELL[1].to_string(),
+                    format!("{} \"$@\"", shell),
+                    SHELL[0].to_string(),
+                ],
+            ),
+            Some(shell) => (
+                SHELL[0].to_string(),
+                vec![SHELL[1].to_string(), shell.clone()],
+            ),
+            None => (config.command, config.options),
+        };
         Self {
-            command: config.command,
-            options: config.options,
+            command,
+            digest: config.digest,
+            options,
+            env: config.env,
             includes: config.includes,
             excludes: [global.excludes.clone(), config.excludes].concat(),
             work_dir: config.work_dir,
+            create_work_dir: config.create_work_dir,
             exclude_submodules: config.exclude_submodules,
+            ignore_rules: IgnoreRules::from_config(global),
             single_file: config.single_file,
-            check_hash: config.check_hash,
+            max_procs: config.max_procs,
+            argfile: config.argfile,
+            sandbox: config.sandbox,
+            pty: config.pty,
+            max_passes: if config.retry_until_stable {
+                config.max_passes.unwrap_or(DEFAULT_MAX_PASSES)
+            } else {
+                1
+            },
+            retries: config.retries,
+            retry_on_exit_codes: config.retry_on_exit_codes,
+            timeout: config.timeout.map(Duration::from_secs),
+            kill_signal: config.kill_signal,
+            kill_grace: config
+                .kill_grace
+                .map_or(DEFAULT_KILL_GRACE, Duration::from_secs),
+            stream: false,
+            prefix: None,
+            snapshot: false,
+            uncommitted_only: config.uncommitted_only,
+            hash: match config.hash_algorithm {
+                Some(algorithm) => Some(algorithm),
+                // a formatter rewriting files unchanged would never be stable by mtime
+                None if config.check_hash || config.retry_until_stable => {
+                    Some(HashAlgorithm::default())
+                }
+                None => None,
+            },
+            input_encoding: config.input_encoding,
+            max_file_size: config.max_file_size.or(global.max_file_size),
+            post_run: config.post_run,
+            stdout_formats: stream_formats(
+                config.error_format_stdout,
+                &config.error_format,
+                config.parse_stream != ParseStream::Stderr,
+            ),
+            stderr_formats: stream_formats(
+                config.error_format_stderr,
+                &config.error_format,
+                config.parse_stream != ParseStream::Stdout,
+            ),
+            note_formats: config.note_format,
+            output: config.output,
+            json_fields: config.json_fields,
+            output_filter: config.output_filter,
+            scope: vec![],
+            check_integrity: false,
+            strict: false,
+            fix: false,
+            semaphore: None,
+            given: None,
+            index: None,
+            max_walk_time: None,
+            last_walk: Cell::new(None),
+            excluded: Cell::new(0),
+            newer_than: None,
+            changed_since: None,
+            cache: global.cache,
+        }
+    }
+
+    /// Restricts processed files to those matching any of the glob patterns
+    pub fn scope<I, S>(&mut self, patterns: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for p in patterns {
+            self.scope.push(p.as_ref().to_string());
         }
+        self
+    }
+
+    /// Detects files modified by the linter outside its inputs, which fail the run if `strict`
+    ///
+    /// This assumes that the linter only touches the files passed as arguments. The whole tree is
+    /// walked before and after each run, comparing only the mtime of the other files so that the
+    /// check costs a stat per file even with `check_hash`.
+    pub fn check_integrity(&mut self, strict: bool) -> &mut Self {
+        self.check_integrity = true;
+        self.strict = strict;
+        self
+    }
+
+    /// Counts modifications of the inputs as fixes rather than failures
+    pub fn fix(&mut self) -> &mut Self {
+        self.fix = true;
+        self
+    }
+
+    /// Runs the linter and `post_run` holding a permit of the semaphore shared with other linters
+    pub fn semaphore(&mut self, semaphore: Arc<Semaphore>) -> &mut Self {
+        self.semaphore = Some(semaphore);
+        self
+    }
+
+    /// Restricts processed files to those modified after the time
+    pub fn newer_than(&mut self, time: SystemTime) -> &mut Self {
+        self.newer_than = Some(time);
+        self
+    }
+
+    /// Restricts processed files to those that differ from the git revision or are untracked
+    ///
+    /// Running fails if the revision cannot be compared, rather than processing all files.
+    pub fn changed_since(&mut self, rev: &str) -> &mut Self {
+        self.changed_since = Some(rev.to_string());
+        self
+    }
+
+    /// Keeps the contents of the files the linter modifies, to revert them later
+    pub fn snapshot(&mut self) -> &mut Self {
+        self.snapshot = true;
+        self
+    }
+
+    /// Lists the command lines that `run` would run, split into batches, without running them
+    ///
+    /// Returns `None` if no files matched like `run`. The response file of `argfile` is removed
+    /// before returning.
+    pub fn dry_run(&self, root: impl AsRef<Path>) -> Result<Option<Vec<Vec<OsString>>>> {
+        let matched = self.matched_paths(&root).map_err(MultilintError::Walk)?;
+        let files = self
+            .filter_paths(&root, matched)
+            .map_err(MultilintError::Walk)?;
+        if !self.includes.is_empty() && files.is_empty() {
+            return Ok(None);
+        }
+        let paths: Vec<&Path> = files.iter().map(PathBuf::as_path).collect();
+        let (cmd, _argfile) = self.command(&root, &paths)?;
+        Ok(Some(cmd.commands()?))
+    }
+
+    /// Copies the output of the linter to the terminal as it is written, still capturing it
+    pub fn stream(&mut self) -> &mut Self {
+        self.stream = true;
+        self
+    }
+
+    /// Prefixes each line of the output copied by `stream` with `[prefix] `
+    pub fn prefix_lines(&mut self, prefix: impl Into<String>) -> &mut Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Sends the signal to runs exceeding the timeout instead of that of the config
+    pub fn kill_signal(&mut self, signal: &str) -> &mut Self {
+        self.kill_signal = Some(signal.to_string());
+        self
+    }
+
+    /// Takes the files matching the includes and excludes from the list instead of walking the tree
+    pub fn given_paths(&mut self, paths: Arc<Vec<PathBuf>>) -> &mut Self {
+        self.given = Some(paths);
+        self
+    }
+
+    /// Lists files from the index instead of walking the tree
+    pub fn index(&mut self, index: Arc<Index>) -> &mut Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Reports walks listing the files for longer than `max` through `slow_walk`
+    pub fn max_walk_time(&mut self, max: Duration) -> &mut Self {
+        self.max_walk_time = Some(max);
+        self
+    }
+
+    /// Returns the time and the number of files of the last walk if it exceeded `max_walk_time`
+    pub fn slow_walk(&self) -> Option<(Duration, usize)> {
+        let max = self.max_walk_time?;
+        self.last_walk.get().filter(|(elapsed, _)| *elapsed > max)
+    }
+
+    /// Returns the number of files matching the includes if the last run found no files only
+    /// because the excludes removed all of them
+    pub fn all_excluded(&self) -> Option<usize> {
+        Some(self.excluded.get()).filter(|&count| count > 0)
+    }
+
+    /// Directory the linter runs in
+    pub fn work_dir(&self) -> &Path {
+        if self.work_dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.work_dir
+        }
+    }
+
+    /// Program run by the linter, the shell with `shell`
+    pub fn program(&self) -> &str {
+        &self.command
     }
 
     pub fn is_executable(&self) -> bool {
@@ -106,91 +549,663 @@ impl Linter {
         I: IntoIterator<Item = P>,
         P: AsRef<Path>,
     {
+        if !self.is_executable() {
+            return Err(MultilintError::CommandNotFound(self.command.clone()));
+        }
+        let parsers = self.parsers().map_err(MultilintError::Config)?;
+        let filters = self.output_filters().map_err(MultilintError::Config)?;
+        self.check_kill_signal()?;
+        Ok(self.execute(root, files, &parsers, &filters)?)
+    }
+
+    /// Parsers of stdout and stderr, where stdout holds JSON with `output = "json"`
+    fn parsers(&self) -> anyhow::Result<(StreamParser, StreamParser)> {
+        let parser = |formats| Parser::new(formats)?.notes(&self.note_formats);
+        let stdout = match self.output {
+            OutputKind::Text => StreamParser::Text(parser(&self.stdout_formats)?),
+            OutputKind::Json => StreamParser::Json(JsonParser::new(self.json_fields.clone())),
+        };
+        Ok((stdout, StreamParser::Text(parser(&self.stderr_formats)?)))
+    }
+
+    /// Identifies the settings of the linter in the cache, or `None` if its results are not cached
+    ///
+    /// Linters without includes do not take files, so their inputs cannot be compared.
+    fn cache_key(&self) -> Option<String> {
+        if !self.cache || self.includes.is_empty() {
+            return None;
+        }
+        let work_dir = self.work_dir.to_string_lossy();
+        let parts = [&self.digest, &self.command, &*work_dir];
+        // the variables may expand differently in each run
+        let env: Vec<String> = self
+            .env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, expand_env(value)))
+            .collect();
+        Some(cache::key(
+            parts
+                .into_iter()
+                .chain(self.options.iter().map(String::as_str))
+                .chain(env.iter().map(String::as_str)),
+        ))
+    }
+
+    /// Builds the output of a cached run over the files
+    fn replay(&self, record: Record, files: usize) -> Result<Output> {
+        let (stdout_parser, stderr_parser) = self.parsers().map_err(MultilintError::Config)?;
+        let mut parsed = stderr_parser.parse_bytes(&record.stderr);
+        parsed.extend(stdout_parser.parse_bytes(&record.stdout));
+        let status = xargs::exit_status(record.exit_code);
+        let failure = match (record.failure_code, record.signal) {
+            (Some(code), _) => Some(xargs::exit_status(code)),
+            #[cfg(unix)]
+            (None, Some(signal)) => Some(xargs::signal_status(signal)),
+            _ => Some(status).filter(|status| !status.success()),
+        };
+        Ok(Output {
+            process: process::Output {
+                status,
+                stdout: record.stdout,
+                stderr: record.stderr,
+            },
+            modified: vec![],
+            modified_outside: vec![],
+            strict: self.strict,
+            fix: self.fix,
+            duration: Duration::ZERO,
+            stats: Stats {
+                matched: files,
+                files,
+                ..Default::default()
+            },
+            parsed,
+            timed_out: false,
+            unstable: false,
+            cached: true,
+            originals: vec![],
+            failure,
+        })
+    }
+
+    fn output_filters(&self) -> anyhow::Result<Vec<Regex>> {
+        self.output_filter
+            .iter()
+            .map(|f| Regex::new(f).with_context(|| format!("Invalid output_filter \"{}\"", f)))
+            .collect()
+    }
+
+    fn execute<I, P>(
+        &self,
+        root: impl AsRef<Path>,
+        files: I,
+        (stdout_parser, stderr_parser): &(StreamParser, StreamParser),
+        filters: &[Regex],
+    ) -> anyhow::Result<Output>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let start = Instant::now();
         let work_dir = if self.work_dir.as_os_str().is_empty() {
             None
         } else {
             Some(&self.work_dir)
         };
 
+        // the inputs are copied rather than read, as only the modified ones are kept
+        let backups = match self.snapshot {
+            true => Some(tempfile::tempdir()?),
+            false => None,
+        };
         let mut entries = Vec::new();
-        for f in files {
-            entries.push(Entry::new(f, self.check_hash)?);
+        for (i, f) in files.into_iter().enumerate() {
+            let backup = backups.as_ref().map(|dir| dir.path().join(i.to_string()));
+            entries.push(Entry::new(f, self.hash, self.input_encoding, backup)?);
         }
 
-        let mut cmd = Xargs::new(&self.command, if self.single_file { Some(1) } else { None });
-        cmd.common_args(&self.options);
-        for e in &entries {
-            let path = if work_dir.is_some() {
-                fs::canonicalize(root.as_ref().join(&e.path))?.to_path_buf()
-            } else {
-                e.path.to_path_buf()
-            };
-            cmd.arg(path);
-        }
         if let Some(work_dir) = &work_dir {
+            if self.create_work_dir && !work_dir.exists() {
+                debug!("creating {}", work_dir.display());
+                fs::create_dir_all(work_dir)
+                    .with_context(|| format!("Cannot create {}", work_dir.display()))?;
+            }
             ensure!(
                 work_dir.is_dir(),
                 "{} is not a directory",
                 work_dir.display()
             );
-            cmd.current_dir(work_dir);
         }
-        let output = cmd.output()?;
+        let paths: Vec<&Path> = entries.iter().map(|e| e.path.as_path()).collect();
+        // the response file is removed when dropped after the run
+        let (mut cmd, argfile) = self.command(&root, &paths)?;
+        if let Some(timeout) = self.timeout {
+            cmd.timeout(timeout);
+        }
+        if let Some(signal) = &self.kill_signal {
+            cmd.kill_signal(xargs::parse_signal(signal)?, self.kill_grace);
+        }
+        if let Some(semaphore) = &self.semaphore {
+            cmd.semaphore(semaphore.clone());
+        }
+        if self.pty {
+            cmd.pty();
+        }
+        if self.stream {
+            cmd.stream();
+        }
+        if let Some(prefix) = &self.prefix {
+            cmd.prefix_lines(prefix);
+        }
+        let mut output = cmd.output()?;
+        // the files are compared with their state before the first attempt
+        let mut attempt = 0;
+        while attempt < self.retries && self.is_transient(&output) {
+            attempt += 1;
+            debug!(
+                "retry {} of {}: {} exited with {}",
+                attempt, self.retries, self.command, output.process.status
+            );
+            output = cmd.output()?;
+        }
+        drop(argfile);
+        if !filters.is_empty() {
+            output.process.stdout = filter_lines(&output.process.stdout, filters);
+            output.process.stderr = filter_lines(&output.process.stderr, filters);
+        }
 
         let mut modified = Vec::new();
+        let mut originals = Vec::new();
         for e in &entries {
             if !e.is_same()? {
-                modified.push(e.path.to_owned())
+                modified.push(e.path.to_owned());
+                if let Some(backup) = &e.backup {
+                    originals.push((e.path.clone(), fs::read(backup)?));
+                }
             }
         }
         debug!("modified: {:?}", &modified);
 
+        let mut parsed = stderr_parser.parse_bytes(&output.process.stderr);
+        parsed.extend(stdout_parser.parse_bytes(&output.process.stdout));
+
         Ok(Output {
-            process: output,
+            process: output.process,
+            stats: Stats {
+                matched: entries.len(),
+                files: entries.len(),
+                batches: output.batches,
+                modified: modified.len(),
+                passes: 1,
+            },
             modified,
+            modified_outside: vec![],
+            strict: self.strict,
+            fix: self.fix,
+            duration: start.elapsed(),
+            parsed,
+            timed_out: output.timed_out,
+            unstable: false,
+            cached: false,
+            failure: output.failure,
+            originals,
         })
     }
 
+    /// Replaces `{root}` in the options with the absolute path of the root
+    fn expand_options(&self, root: impl AsRef<Path>) -> anyhow::Result<Vec<String>> {
+        if !self.options.iter().any(|o| o.contains("{root}")) {
+            return Ok(self.options.clone());
+        }
+        let root = fs::canonicalize(root)?;
+        if root.to_str().is_none() {
+            warn!("{{root}} is not valid UTF-8: {}", root.display());
+        }
+        let root = root.to_string_lossy();
+        Ok(self
+            .options
+            .iter()
+            .map(|o| o.replace("{root}", &root))
+            .collect())
+    }
+
     pub fn run(&self, root: impl AsRef<Path>) -> Result<Option<Output>> {
-        let files = self.paths(&root)?;
+        let matched = self.matched_paths(&root).map_err(MultilintError::Walk)?;
+        let matched_count = matched.len();
+        let files = self
+            .filter_paths(&root, matched)
+            .map_err(MultilintError::Walk)?;
         if !self.includes.is_empty() && files.is_empty() {
             debug!("no files");
+            let excluded = match matched_count == 0 && !self.excludes.is_empty() {
+                true => self.count_unexcluded(&root).map_err(MultilintError::Walk)?,
+                false => 0,
+            };
+            self.excluded.set(excluded);
+            return Ok(None);
+        }
+        self.excluded.set(0);
+
+        let cache = match self.cache_key() {
+            Some(key) => Some(ResultCache::new(&root, &key, &files)?),
+            None => None,
+        };
+        if let Some(record) = cache.as_ref().and_then(ResultCache::load) {
+            debug!("replaying the cached result");
+            let mut output = self.replay(record, files.len())?;
+            output.stats.matched = matched_count;
+            return Ok(Some(output));
+        }
+
+        let mut others = Vec::new();
+        if self.check_integrity {
+            let inputs: HashSet<_> = files.iter().collect();
+            for path in self.walk(&root, None).map_err(MultilintError::Walk)? {
+                if !inputs.contains(&path) {
+                    others.push(Entry::new(path, None, self.input_encoding, None)?);
+                }
+            }
+        }
+
+        let mut output = if self.sandbox {
+            self.run_sandboxed(&root, &files)?
+        } else {
+            self.run_until_stable(&root, &files)?
+        };
+        output.stats.matched = matched_count;
+        for e in &others {
+            // the linter may have deleted the file
+            if !e.path.exists() || !e.is_same()? {
+                warn!("{} was modified outside of the inputs", e.path.display());
+                output.modified_outside.push(e.path.to_owned());
+            }
+        }
+        // runs that modified files are not reproduced by replaying their output
+        if let (Some(cache), Some(code)) = (&cache, output.exit_code()) {
+            if !output.timed_out && output.modified.is_empty() && output.modified_outside.is_empty()
+            {
+                let (failure_code, signal) = (output.failure_code(), output.signal());
+                let stored =
+                    cache.store(code, failure_code, signal, output.stdout(), output.stderr());
+                if let Err(e) = stored {
+                    warn!("{:#}", e);
+                }
+            }
+        }
+        if let Some(post) = self.post_run(&root, &output.modified)? {
+            output.process.stdout.extend(post.stdout);
+            output.process.stderr.extend(post.stderr);
+            // a failing hook fails this linter rather than the whole run
+            if !post.status.success() {
+                let message = format!(
+                    "post_run \"{}\" failed: {}\n",
+                    self.post_run.join(" "),
+                    post.status
+                );
+                output.process.stderr.extend(message.into_bytes());
+                if output.process.status.success() {
+                    output.process.status = post.status;
+                    output.failure = Some(post.status);
+                }
+            }
+        }
+        Ok(Some(output))
+    }
+
+    /// Runs the linter again while it modifies files, up to `max_passes` times
+    ///
+    /// The output is that of the last pass, with the files modified in any pass.
+    fn run_until_stable(&self, root: impl AsRef<Path>, files: &[PathBuf]) -> Result<Output> {
+        let mut output = self.run_files(&root, files)?;
+        let mut changed = !output.modified.is_empty();
+        while changed && !output.timed_out && output.stats.passes < self.max_passes {
+            let mut next = self.run_files(&root, files)?;
+            changed = !next.modified.is_empty();
+            debug!(
+                "pass {}: {} files modified",
+                output.stats.passes + 1,
+                next.modified.len()
+            );
+            let mut modified = output.modified;
+            for path in next.modified {
+                if !modified.contains(&path) {
+                    modified.push(path);
+                }
+            }
+            next.stats.passes += output.stats.passes;
+            next.stats.batches += output.stats.batches;
+            next.stats.modified = modified.len();
+            next.duration += output.duration;
+            next.modified = modified;
+            // the contents before the first pass that modified each file
+            for (path, contents) in next.originals {
+                if !output.originals.iter().any(|(p, _)| p == &path) {
+                    output.originals.push((path, contents));
+                }
+            }
+            next.originals = output.originals;
+            output = next;
+        }
+        output.unstable = changed && !output.timed_out && self.max_passes > 1;
+        Ok(output)
+    }
+
+    /// Runs on copies of the files in a sandbox, reporting the originals of modified copies
+    fn run_sandboxed(&self, root: impl AsRef<Path>, files: &[PathBuf]) -> Result<Output> {
+        let sandbox = Sandbox::new()?;
+        let copies = sandbox.copy(&root, files)?;
+        sandbox.copy_context(&root, files)?;
+        let mut linter = self.clone();
+        // the copies are discarded, so there is nothing to revert
+        linter.snapshot = false;
+        if linter.work_dir.as_os_str().is_empty() {
+            linter.work_dir = sandbox.path().to_owned();
+        }
+        let mut output = linter.run_until_stable(sandbox.path(), &copies)?;
+        output.modified = output
+            .modified
+            .iter()
+            .map(|copy| sandbox.original(&root, copy))
+            .collect();
+        Ok(output)
+    }
+
+    /// Runs `post_run` with `{modified}` expanded to the files the linter modified
+    ///
+    /// The command is skipped if it refers to `{modified}` but no files were modified.
+    fn post_run(
+        &self,
+        root: impl AsRef<Path>,
+        modified: &[PathBuf],
+    ) -> anyhow::Result<Option<process::Output>> {
+        let (program, args) = match self.post_run.split_first() {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        let uses_modified = self.post_run.iter().any(|arg| arg == "{modified}");
+        if uses_modified && modified.is_empty() {
+            debug!("post_run skipped");
             return Ok(None);
         }
 
-        Ok(Some(self.run_files(root, files)?))
+        let mut cmd = process::Command::new(program);
+        for arg in args {
+            if arg == "{modified}" {
+                cmd.args(modified);
+            } else {
+                cmd.arg(arg);
+            }
+        }
+        debug!("post_run: {:?}", cmd);
+        let _permit = self.semaphore.as_deref().map(Semaphore::acquire);
+        let output = cmd
+            .current_dir(root)
+            .output()
+            .with_context(|| format!("Cannot run post_run \"{}\"", program))?;
+        Ok(Some(output))
+    }
+
+    /// Checks that all glob patterns compile
+    pub fn check_globs(&self) -> Result<()> {
+        build_override(".", &self.includes, &self.excludes)
+            .and_then(|_| build_override(".", &self.scope, &[]))
+            .map(|_| ())
+            .map_err(MultilintError::Config)
+    }
+
+    /// Validates the regexes of `output_filter`
+    pub fn check_output_filter(&self) -> Result<()> {
+        self.output_filters()
+            .map(|_| ())
+            .map_err(MultilintError::Config)
+    }
+
+    /// Validates the name of `kill_signal`
+    pub fn check_kill_signal(&self) -> Result<()> {
+        match &self.kill_signal {
+            Some(signal) => xargs::parse_signal(signal)
+                .map(|_| ())
+                .map_err(MultilintError::Config),
+            None => Ok(()),
+        }
     }
 
-    fn paths(&self, root: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    /// Lists files matching the includes and excludes
+    pub fn matched_paths(&self, root: impl AsRef<Path>) -> anyhow::Result<Vec<PathBuf>> {
         if self.includes.is_empty() {
             return Ok(vec![]);
         }
 
-        let overrides = {
-            let mut builder = OverrideBuilder::new(&root);
-            for pattern in &self.includes {
-                builder.add(&escape_pattern(pattern))?;
+        let overrides = build_override(&root, &self.includes, &self.excludes)?;
+        // excluded directories are not traversed at all
+        let pruned = build_override(&root, &self.excludes, &[])?;
+
+        let walked = match &self.given {
+            Some(given) => self.filter_given(&root, given, &pruned),
+            None => {
+                let start = Instant::now();
+                let walked = self.walk(&root, Some(pruned))?;
+                self.last_walk.set(Some((start.elapsed(), walked.len())));
+                walked
             }
-            for pattern in &self.excludes {
-                builder.add(&format!("!{}", escape_pattern(pattern)))?;
+        };
+        let matched: Vec<PathBuf> = walked
+            .into_iter()
+            .filter(|path| match overrides.matched(path, false) {
+                Match::Whitelist(_) => true,
+                Match::None => false,
+                Match::Ignore(i) => {
+                    debug!("ignoring {}: {:?}", path.display(), i);
+                    false
+                }
+            })
+            .collect();
+        for path in matched.iter().filter(|path| path.to_str().is_none()) {
+            // the linter gets the exact path, but reports can only show it lossily
+            warn!("{} is not valid UTF-8", path.display());
+        }
+        Ok(matched)
+    }
+
+    /// Assembles the command with the files as arguments, or in a response file with `argfile`
+    fn command(
+        &self,
+        root: impl AsRef<Path>,
+        files: &[&Path],
+    ) -> anyhow::Result<(Xargs, Option<NamedTempFile>)> {
+        let max_args = if self.single_file { Some(1) } else { None };
+        let mut cmd = Xargs::new(&self.command, max_args, self.max_procs);
+        cmd.common_args(self.expand_options(&root)?);
+        for (key, value) in &self.env {
+            cmd.env(key, expand_env(value));
+        }
+        let work_dir = Some(&self.work_dir).filter(|dir| !dir.as_os_str().is_empty());
+        let mut paths = Vec::new();
+        for path in files {
+            let path = if work_dir.is_some() {
+                fs::canonicalize(root.as_ref().join(path))?.to_path_buf()
+            } else {
+                path.to_path_buf()
+            };
+            paths.push(path);
+        }
+        let mut argfile = None;
+        if self.argfile && !self.single_file {
+            let mut file = NamedTempFile::new()?;
+            for path in &paths {
+                writeln!(file, "{}", path.display())?;
             }
-            builder.build()?
+            file.flush()?;
+            let mut arg = OsString::from("@");
+            arg.push(file.path());
+            cmd.arg(arg);
+            argfile = Some(file);
+        } else {
+            cmd.args(paths);
+        }
+        if let Some(work_dir) = work_dir {
+            cmd.current_dir(work_dir);
+        }
+        Ok((cmd, argfile))
+    }
+
+    /// Whether a failed run may pass when retried, by `retry_on_exit_codes` if set
+    ///
+    /// The code is that of the failing batch, and runs killed for exceeding the timeout are not
+    /// retried, as they would likely time out again.
+    fn is_transient(&self, output: &xargs::Output) -> bool {
+        let failure = match output.failure {
+            Some(failure) if !output.timed_out => failure,
+            _ => return false,
         };
+        match failure.code() {
+            Some(code) if !self.retry_on_exit_codes.is_empty() => {
+                self.retry_on_exit_codes.contains(&code)
+            }
+            _ => self.retry_on_exit_codes.is_empty(),
+        }
+    }
 
-        let mut walk = WalkBuilder::new(&root);
-        walk.hidden(false)
-            .overrides(OverrideBuilder::new(&root).add("!.git/")?.build()?);
-        if self.exclude_submodules {
-            walk.filter_entry(|entry| {
-                if let Some(file_type) = entry.file_type() {
-                    // this method must cover most cases
-                    if file_type.is_dir() && entry.path().join(".git").is_file() {
+    /// Counts the files that would match the includes without the excludes
+    fn count_unexcluded(&self, root: impl AsRef<Path>) -> anyhow::Result<usize> {
+        let mut linter = self.clone();
+        linter.excludes.clear();
+        linter.max_walk_time = None;
+        Ok(linter.matched_paths(root)?.len())
+    }
+
+    /// Keeps the given files, relative to the root, that exist outside excluded directories
+    fn filter_given(
+        &self,
+        root: impl AsRef<Path>,
+        given: &[PathBuf],
+        pruned: &Override,
+    ) -> Vec<PathBuf> {
+        let root = root.as_ref();
+        given
+            .iter()
+            .map(|path| {
+                let path: PathBuf = path
+                    .components()
+                    .filter(|c| *c != Component::CurDir)
+                    .collect();
+                let path = root.join(path);
+                path.strip_prefix(".").unwrap_or(&path).to_path_buf()
+            })
+            .filter(|path| {
+                if !path.is_file() {
+                    debug!("ignoring {}: not a file", path.display());
+                    return false;
+                }
+                let relative = path.strip_prefix(root).unwrap_or(path);
+                let pruned = relative
+                    .ancestors()
+                    .skip(1)
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .map(|dir| root.join(dir))
+                    .any(|dir| {
+                        pruned.matched(&dir, true).is_whitelist()
+                            || (self.exclude_submodules && git::is_submodule(&dir))
+                    });
+                if pruned {
+                    debug!("ignoring {}: in an excluded directory", path.display());
+                }
+                !pruned
+            })
+            .collect()
+    }
+
+    /// Applies the scope and the size limit to matched files
+    fn filter_paths(
+        &self,
+        root: impl AsRef<Path>,
+        paths: Vec<PathBuf>,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let scope = if self.scope.is_empty() {
+            None
+        } else {
+            Some(build_override(&root, &self.scope, &[])?)
+        };
+        let uncommitted = if self.uncommitted_only {
+            let uncommitted = git::uncommitted(&root);
+            if uncommitted.is_none() {
+                warn!("not in a git repository, processing all files");
+            }
+            uncommitted
+        } else {
+            None
+        };
+        let changed = match &self.changed_since {
+            Some(rev) => Some(git::changed(&root, rev).with_context(|| {
+                format!(
+                    "Cannot diff against \"{}\" with git, which needs a git work tree and a valid revision",
+                    rev
+                )
+            })?),
+            None => None,
+        };
+
+        Ok(paths
+            .into_iter()
+            .filter(|path| match &scope {
+                Some(scope) => scope.matched(path, false).is_whitelist(),
+                None => true,
+            })
+            .filter(|path| match &uncommitted {
+                Some(uncommitted) => fs::canonicalize(path).is_ok_and(|p| uncommitted.contains(&p)),
+                None => true,
+            })
+            .filter(|path| match &changed {
+                Some(changed) => fs::canonicalize(path).is_ok_and(|p| changed.contains(&p)),
+                None => true,
+            })
+            .filter(|path| match self.newer_than {
+                Some(time) => fs::metadata(path)
+                    .and_then(|m| m.modified())
+                    .map_or(true, |modified| modified > time),
+                None => true,
+            })
+            .filter(|path| match self.max_file_size {
+                Some(max_file_size) => match fs::metadata(path) {
+                    Ok(metadata) if metadata.len() > max_file_size => {
+                        debug!("ignoring {}: {} bytes", path.display(), metadata.len());
+                        false
+                    }
+                    _ => true,
+                },
+                None => true,
+            })
+            .collect())
+    }
+
+    /// Lists all files under the root, skipping directories matched by `pruned`
+    fn walk(
+        &self,
+        root: impl AsRef<Path>,
+        pruned: Option<Override>,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        if let Some(index) = &self.index {
+            return Ok(index.files(&root, pruned.as_ref(), self.exclude_submodules));
+        }
+        let mut walk = self.ignore_rules.walk(root.as_ref())?;
+        let exclude_submodules = self.exclude_submodules;
+        walk.filter_entry(move |entry| {
+            if let Some(file_type) = entry.file_type() {
+                if !file_type.is_dir() {
+                    return true;
+                }
+                if exclude_submodules && git::is_submodule(entry.path()) {
+                    return false;
+                }
+                if let Some(pruned) = &pruned {
+                    if pruned.matched(entry.path(), true).is_whitelist() {
+                        debug!("pruning {}", entry.path().display());
                         return false;
                     }
                 }
-                true
-            });
-        }
+            }
+            true
+        });
         Ok(walk
             .build()
             .filter_map(|entry| -> Option<DirEntry> {
@@ -216,20 +1231,76 @@ impl Linter {
                 }
                 None
             })
-            .filter(|path| match overrides.matched(path, false) {
-                Match::Whitelist(_) => true,
-                Match::None => false,
-                Match::Ignore(i) => {
-                    debug!("ignoring {}: {:?}", path.display(), i);
-                    false
-                }
-            })
             .collect())
     }
 }
 
+fn build_override(
+    root: impl AsRef<Path>,
+    includes: &[String],
+    excludes: &[String],
+) -> anyhow::Result<Override> {
+    let mut builder = OverrideBuilder::new(root);
+    for pattern in includes {
+        builder.add(&escape_pattern(pattern))?;
+    }
+    for pattern in excludes {
+        builder.add(&format!("!{}", escape_pattern(pattern)))?;
+    }
+    Ok(builder.build()?)
+}
+
+/// Replaces `${VAR}` with the environment variable of this process, or nothing if it is unset
+fn expand_env(value: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let name = &rest[start + 2..start + 2 + len];
+        expanded.push_str(&env::var(name).unwrap_or_default());
+        rest = &rest[start + 3 + len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Returns the error formats for a stream, falling back to the shared ones if it is parsed
+fn stream_formats(own: Vec<String>, shared: &[String], parsed: bool) -> Vec<String> {
+    if !own.is_empty() {
+        own
+    } else if !parsed {
+        vec![]
+    } else if !shared.is_empty() {
+        shared.to_vec()
+    } else {
+        GNU_FORMATS.iter().map(|f| f.to_string()).collect()
+    }
+}
+
+/// Drops the lines matching any of the filters, keeping their line endings
+fn filter_lines(text: &[u8], filters: &[Regex]) -> Vec<u8> {
+    text.split_inclusive(|&b| b == b'\n')
+        .filter(|line| {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            !filters.iter().any(|f| f.is_match(line))
+        })
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/// Converts a config glob to an override pattern
+///
+/// Globs follow gitignore rules. A leading `./` anchors the glob to the root like `/`, and `!` is
+/// matched literally since inclusion and exclusion are separate lists.
 fn escape_pattern(glob: &str) -> String {
-    if glob.starts_with('!') {
+    if let Some(anchored) = glob.strip_prefix("./") {
+        format!("/{}", anchored)
+    } else if glob.starts_with('!') {
         format!("\\{}", glob)
     } else {
         glob.to_string()
@@ -238,13 +1309,19 @@ fn escape_pattern(glob: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::Linter;
-    use crate::config::LinterConfig;
+    use super::{decode, expand_env, Linter};
+    use crate::{
+        config::{GlobalConfig, HashAlgorithm, InputEncoding, LinterConfig, ParseStream},
+        error::MultilintError,
+    };
     use std::{
         default::Default,
-        fs::{create_dir, read_to_string, File},
+        fs::{self, create_dir, create_dir_all, read_to_string, File},
         io::Write,
+        path::{Path, PathBuf},
         process,
+        sync::Arc,
+        time::{Duration, SystemTime},
     };
     use tempfile::tempdir;
     use test_log::test;
@@ -272,6 +1349,630 @@ mod tests {
         );
     }
 
+    #[test]
+    fn scope() {
+        let root = tempdir().unwrap();
+        create_dir(root.path().join("src")).unwrap();
+        File::create(root.path().join("main.rs")).unwrap();
+        File::create(root.path().join("src").join("lib.rs")).unwrap();
+        let mut linter = Linter::from_config(
+            LinterConfig {
+                command: "echo".to_string(),
+                includes: vec!["*.rs".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        linter.scope(["src/**"]);
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.success());
+        assert_eq!(
+            std::str::from_utf8(output.stdout()).unwrap().trim_end(),
+            root.path().join("src").join("lib.rs").display().to_string()
+        );
+    }
+
+    #[test]
+    fn uncommitted_only() {
+        let root = tempdir().unwrap();
+        File::create(root.path().join("main.rs")).unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "true".to_string(),
+                includes: vec!["*.rs".to_string()],
+                uncommitted_only: true,
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        // outside a repository, all files are processed
+        assert_eq!(linter.run(&root).unwrap().unwrap().stats().files, 1);
+
+        let git = |args: &[&str]| {
+            let cmd = process::Command::new("git")
+                .current_dir(&root)
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(cmd.status.success(), "{:?}", cmd);
+        };
+        git(&["init"]);
+        git(&["config", "user.name", "test"]);
+        git(&["config", "user.email", "test"]);
+        git(&["add", "."]);
+        git(&["commit", "-m", "init"]);
+        assert!(linter.run(&root).unwrap().is_none());
+
+        File::create(root.path().join("new.rs")).unwrap();
+        fs::write(root.path().join("main.rs"), "fn main() {}").unwrap();
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stats().files, 2);
+        assert_eq!(output.stats().matched, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cache() {
+        let root = tempdir().unwrap();
+        fs::write(root.path().join("a.rs"), "fn a() {}").unwrap();
+        fs::write(root.path().join("b.rs"), "fn b() {}").unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec![
+                    "-c".to_string(),
+                    "echo >> \"$0/runs\"; echo a.rs:1: bad; exit 3".to_string(),
+                    "{root}".to_string(),
+                ],
+                includes: vec!["*.rs".to_string()],
+                single_file: true,
+                ..Default::default()
+            },
+            &GlobalConfig {
+                cache: true,
+                ..Default::default()
+            },
+        );
+        let runs = || read_to_string(root.path().join("runs")).unwrap().len();
+
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(!output.cached());
+        let replayed = linter.run(&root).unwrap().unwrap();
+        assert!(replayed.cached());
+        assert_eq!(runs(), 2);
+        assert_eq!(replayed.stdout(), output.stdout());
+        assert_eq!(replayed.exit_code(), Some(123));
+        assert_eq!(replayed.failure_code(), Some(3));
+        assert!(replayed.process_failed());
+        assert_eq!(replayed.parsed(), output.parsed());
+        assert_eq!(replayed.stats().files, 2);
+
+        fs::write(root.path().join("a.rs"), "fn b() {}").unwrap();
+        assert!(!linter.run(&root).unwrap().unwrap().cached());
+        assert_eq!(runs(), 4);
+    }
+
+    #[test]
+    fn newer_than() {
+        let root = tempdir().unwrap();
+        let now = SystemTime::now();
+        let old = File::create(root.path().join("old.rs")).unwrap();
+        old.set_modified(now - Duration::from_secs(60)).unwrap();
+        File::create(root.path().join("new.rs")).unwrap();
+        let mut linter = Linter::from_config(
+            LinterConfig {
+                command: "true".to_string(),
+                includes: vec!["*.rs".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        assert_eq!(linter.run(&root).unwrap().unwrap().stats().files, 2);
+
+        linter.newer_than(now - Duration::from_secs(30));
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stats().files, 1);
+        assert_eq!(output.stats().matched, 2);
+
+        linter.newer_than(now + Duration::from_secs(60));
+        assert!(linter.run(&root).unwrap().is_none());
+    }
+
+    #[test]
+    fn given_paths() {
+        let root = tempdir().unwrap();
+        create_dir_all(root.path().join("gen")).unwrap();
+        for name in ["a.rs", "b.rs", "c.txt", "gen/d.rs"] {
+            File::create(root.path().join(name)).unwrap();
+        }
+        let mut linter = Linter::from_config(
+            LinterConfig {
+                command: "echo".to_string(),
+                includes: vec!["*.rs".to_string()],
+                excludes: vec!["gen/".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let given = ["./a.rs", "c.txt", "gen/d.rs", "missing.rs"];
+        linter.given_paths(Arc::new(given.iter().map(PathBuf::from).collect()));
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stats().matched, 1);
+        assert_eq!(
+            std::str::from_utf8(output.stdout()).unwrap(),
+            format!("{}\n", root.path().join("a.rs").display())
+        );
+
+        linter.given_paths(Arc::new(vec![PathBuf::from("c.txt")]));
+        assert!(linter.run(&root).unwrap().is_none());
+    }
+
+    #[test]
+    fn max_file_size() {
+        let root = tempdir().unwrap();
+        File::create(root.path().join("small.rs")).unwrap();
+        {
+            let mut file = File::create(root.path().join("large.rs")).unwrap();
+            write!(&mut file, "fn main() {{}}").unwrap();
+        }
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "echo".to_string(),
+                includes: vec!["*.rs".to_string()],
+                max_file_size: Some(1),
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(
+            std::str::from_utf8(output.stdout()).unwrap().trim_end(),
+            root.path().join("small.rs").display().to_string()
+        );
+        assert_eq!(output.stats().matched, 2);
+        assert_eq!(output.stats().files, 1);
+        assert_eq!(output.stats().batches, 1);
+    }
+
+    #[test]
+    fn prune_directories() {
+        let root = tempdir().unwrap();
+        create_dir(root.path().join("gen")).unwrap();
+        create_dir(root.path().join("src")).unwrap();
+        File::create(root.path().join("gen").join("a.rs")).unwrap();
+        File::create(root.path().join("src").join("gen")).unwrap();
+        let test = |excludes: &[&str]| -> Vec<String> {
+            let linter = Linter::from_config(
+                LinterConfig {
+                    command: "echo".to_string(),
+                    includes: vec!["*".to_string()],
+                    excludes: excludes.iter().map(|s| s.to_string()).collect(),
+                    ..Default::default()
+                },
+                &Default::default(),
+            );
+            let output = match linter.run(&root).unwrap() {
+                Some(output) => output,
+                None => return vec![],
+            };
+            let mut files: Vec<String> = std::str::from_utf8(output.stdout())
+                .unwrap()
+                .split_whitespace()
+                .map(|f| {
+                    Path::new(f)
+                        .strip_prefix(&root)
+                        .unwrap()
+                        .display()
+                        .to_string()
+                })
+                .collect();
+            files.sort();
+            files
+        };
+
+        // directory-only patterns exclude the contents of directories but not files
+        assert_eq!(
+            test(&["gen/"]),
+            vec![Path::new("src").join("gen").display().to_string()]
+        );
+        // other patterns exclude both
+        assert!(test(&["gen"]).is_empty());
+        assert_eq!(
+            test(&["gen/**"]),
+            vec![Path::new("src").join("gen").display().to_string()]
+        );
+    }
+
+    #[test]
+    fn globs() {
+        let root = tempdir().unwrap();
+        create_dir_all(root.path().join("src").join("a")).unwrap();
+        File::create(root.path().join("main.rs")).unwrap();
+        File::create(root.path().join("src").join("lib.rs")).unwrap();
+        File::create(root.path().join("src").join("a").join("b.rs")).unwrap();
+        File::create(root.path().join("src").join("a").join("c.txt")).unwrap();
+        let test = |include: &str| -> Vec<String> {
+            let linter = Linter::from_config(
+                LinterConfig {
+                    includes: vec![include.to_string()],
+                    ..Default::default()
+                },
+                &Default::default(),
+            );
+            let mut files: Vec<String> = linter
+                .matched_paths(&root)
+                .unwrap()
+                .iter()
+                .map(|f| {
+                    let f = f.strip_prefix(&root).unwrap();
+                    f.iter()
+                        .map(|c| c.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/")
+                })
+                .collect();
+            files.sort();
+            files
+        };
+
+        let all_rs = vec!["main.rs", "src/a/b.rs", "src/lib.rs"];
+        assert_eq!(test("*.rs"), all_rs);
+        assert_eq!(test("**/*.rs"), all_rs);
+        assert_eq!(
+            test("**"),
+            vec!["main.rs", "src/a/b.rs", "src/a/c.txt", "src/lib.rs"]
+        );
+        assert_eq!(
+            test("src/**"),
+            vec!["src/a/b.rs", "src/a/c.txt", "src/lib.rs"]
+        );
+        assert_eq!(test("src/**/*.rs"), vec!["src/a/b.rs", "src/lib.rs"]);
+        // patterns with a slash are anchored to the root
+        assert_eq!(test("src/*.rs"), vec!["src/lib.rs"]);
+        assert_eq!(test("a/*.rs"), Vec::<String>::new());
+        assert_eq!(test("/main.rs"), vec!["main.rs"]);
+        assert_eq!(test("./src/*.rs"), vec!["src/lib.rs"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let root = tempdir().unwrap();
+        let main = root.path().join(OsStr::from_bytes(b"\xff.rs"));
+        File::create(&main).unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "cat".to_string(),
+                includes: vec!["*.rs".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.success());
+        assert_eq!(output.stats().files, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn argfile() {
+        let root = tempdir().unwrap();
+        File::create(root.path().join("a.rs")).unwrap();
+        File::create(root.path().join("b.rs")).unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec![
+                    "-c".to_string(),
+                    r#"echo "$1"; cat "${1#@}""#.to_string(),
+                    "sh".to_string(),
+                ],
+                includes: vec!["*.rs".to_string()],
+                argfile: true,
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.success());
+        assert_eq!(output.stats().batches, 1);
+        let stdout = std::str::from_utf8(output.stdout()).unwrap();
+        let mut lines: Vec<&str> = stdout.lines().collect();
+        let argfile = lines.remove(0).strip_prefix('@').unwrap();
+        assert!(!Path::new(argfile).exists());
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                root.path().join("a.rs").display().to_string(),
+                root.path().join("b.rs").display().to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_since() {
+        let root = tempdir().unwrap();
+        File::create(root.path().join("main.rs")).unwrap();
+        let mut linter = Linter::from_config(
+            LinterConfig {
+                command: "true".to_string(),
+                includes: vec!["*.rs".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        linter.changed_since("HEAD");
+        let err = format!("{:#}", linter.run(&root).unwrap_err());
+        assert!(err.contains("Cannot diff against \"HEAD\""), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sandbox() {
+        let root = tempdir().unwrap();
+        let main = root.path().join("src").join("main.rs");
+        create_dir(root.path().join("src")).unwrap();
+        fs::write(&main, "original").unwrap();
+        fs::write(root.path().join("lint.toml"), "config").unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec![
+                    "-c".to_string(),
+                    r#"pwd; cat lint.toml; echo modified > "$1""#.to_string(),
+                    "sh".to_string(),
+                ],
+                includes: vec!["*.rs".to_string()],
+                sandbox: true,
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.has_modifications());
+        assert_eq!(output.modified().to_vec(), vec![main.clone()]);
+        assert_eq!(fs::read_to_string(&main).unwrap(), "original");
+        let stdout = std::str::from_utf8(output.stdout()).unwrap();
+        let (sandbox, config) = stdout.split_once('\n').unwrap();
+        assert!(!Path::new(sandbox).exists());
+        assert_eq!(config, "config");
+    }
+
+    #[test]
+    fn slow_walk() {
+        let root = tempdir().unwrap();
+        File::create(root.path().join("main.rs")).unwrap();
+        let mut linter = Linter::from_config(
+            LinterConfig {
+                command: "true".to_string(),
+                includes: vec!["*.rs".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        linter.run(&root).unwrap();
+        assert!(linter.slow_walk().is_none());
+
+        linter.max_walk_time(std::time::Duration::ZERO);
+        linter.run(&root).unwrap();
+        let (_, files) = linter.slow_walk().unwrap();
+        assert_eq!(files, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retries() {
+        let root = tempdir().unwrap();
+        let count = root.path().join("count");
+        // fails with 7 until the third attempt
+        let script = r#"n=$(cat "$0" 2>/dev/null || echo 0); echo $((n + 1)) > "$0"; [ "$n" -ge 2 ] || exit 7"#;
+        let run = |retries, retry_on_exit_codes| {
+            let _ = fs::remove_file(&count);
+            let output = Linter::from_config(
+                LinterConfig {
+                    command: "sh".to_string(),
+                    options: vec![
+                        "-c".to_string(),
+                        script.to_string(),
+                        count.to_string_lossy().into_owned(),
+                    ],
+                    retries,
+                    retry_on_exit_codes,
+                    ..Default::default()
+                },
+                &Default::default(),
+            )
+            .run(&root)
+            .unwrap()
+            .unwrap();
+            (output.success(), read_to_string(&count).unwrap())
+        };
+        assert_eq!(run(0, vec![7]), (false, "1\n".to_string()));
+        assert_eq!(run(1, vec![7]), (false, "2\n".to_string()));
+        assert_eq!(run(5, vec![7]), (true, "3\n".to_string()));
+        assert_eq!(run(5, vec![]), (true, "3\n".to_string()));
+        assert_eq!(run(5, vec![1]), (false, "1\n".to_string()));
+
+        // a run killed for exceeding the timeout is not retried
+        fs::remove_file(&count).unwrap();
+        let output = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec![
+                    "-c".to_string(),
+                    "echo >> \"$0\"; exec sleep 10".to_string(),
+                    count.to_string_lossy().into_owned(),
+                ],
+                retries: 2,
+                timeout: Some(1),
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .run(&root)
+        .unwrap()
+        .unwrap();
+        assert!(output.timed_out());
+        assert_eq!(read_to_string(&count).unwrap(), "\n");
+    }
+
+    #[test]
+    fn dry_run() {
+        let root = tempdir().unwrap();
+        File::create(root.path().join("a.rs")).unwrap();
+        File::create(root.path().join("b.rs")).unwrap();
+        let linter = |includes: &str| {
+            Linter::from_config(
+                LinterConfig {
+                    command: "rustfmt".to_string(),
+                    options: vec!["--check".to_string()],
+                    includes: vec![includes.to_string()],
+                    single_file: true,
+                    ..Default::default()
+                },
+                &Default::default(),
+            )
+        };
+        let mut commands = linter("*.rs").dry_run(&root).unwrap().unwrap();
+        commands.sort();
+        let file = |name: &str| root.path().join(name).into_os_string();
+        assert_eq!(
+            commands,
+            vec![
+                vec!["rustfmt".into(), "--check".into(), file("a.rs")],
+                vec!["rustfmt".into(), "--check".into(), file("b.rs")],
+            ]
+        );
+        assert!(linter("*.txt").dry_run(&root).unwrap().is_none());
+    }
+
+    #[test]
+    fn all_excluded() {
+        let root = tempdir().unwrap();
+        create_dir_all(root.path().join("gen")).unwrap();
+        File::create(root.path().join("gen").join("a.rs")).unwrap();
+        File::create(root.path().join("gen").join("b.rs")).unwrap();
+        let linter = |excludes: &[&str]| {
+            Linter::from_config(
+                LinterConfig {
+                    command: "true".to_string(),
+                    includes: vec!["*.rs".to_string()],
+                    excludes: excludes.iter().map(|e| e.to_string()).collect(),
+                    ..Default::default()
+                },
+                &Default::default(),
+            )
+        };
+        let excluded = linter(&["gen/"]);
+        assert!(excluded.run(&root).unwrap().is_none());
+        assert_eq!(excluded.all_excluded(), Some(2));
+
+        let unmatched = linter(&["*.txt"]);
+        fs::remove_dir_all(root.path().join("gen")).unwrap();
+        assert!(unmatched.run(&root).unwrap().is_none());
+        assert_eq!(unmatched.all_excluded(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retry_until_stable() {
+        let root = tempdir().unwrap();
+        let main = root.path().join("main.rs");
+        fs::write(&main, "").unwrap();
+        // appends a line until there are three
+        let script = r#"[ "$(wc -l < "$1")" -lt 3 ] && echo x >> "$1"; true"#;
+        let config = |retry_until_stable, max_passes| LinterConfig {
+            command: "sh".to_string(),
+            options: vec!["-c".to_string(), script.to_string(), "sh".to_string()],
+            includes: vec!["*.rs".to_string()],
+            retry_until_stable,
+            max_passes,
+            ..Default::default()
+        };
+
+        let linter = Linter::from_config(config(true, None), &Default::default());
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stats().passes, 4);
+        assert_eq!(output.stats().modified, 1);
+        assert!(!output.unstable());
+        assert_eq!(fs::read_to_string(&main).unwrap(), "x\nx\nx\n");
+
+        fs::write(&main, "").unwrap();
+        let linter = Linter::from_config(config(true, Some(2)), &Default::default());
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stats().passes, 2);
+        assert!(output.unstable());
+
+        // rewriting the same contents is stable, as the contents are compared
+        let mut rewrite = config(true, None);
+        rewrite.options[1] = r#"cat "$1" > "$1.tmp" && sleep 0.01 && mv "$1.tmp" "$1""#.to_string();
+        let linter = Linter::from_config(rewrite, &Default::default());
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stats().passes, 1);
+        assert!(!output.has_modifications());
+
+        fs::write(&main, "").unwrap();
+        let linter = Linter::from_config(config(false, None), &Default::default());
+        assert_eq!(linter.run(&root).unwrap().unwrap().stats().passes, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout() {
+        let root = tempdir().unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec!["-c".to_string(), "echo started; exec sleep 10".to_string()],
+                timeout: Some(1),
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.timed_out());
+        assert!(output.process_failed());
+        assert_eq!(output.exit_code(), Some(124));
+        assert_eq!(output.stdout(), b"started\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_filter() {
+        let root = tempdir().unwrap();
+        let config = |output_filter: &[&str]| LinterConfig {
+            command: "sh".to_string(),
+            options: vec![
+                "-c".to_string(),
+                "echo 'Checking 1/2'; echo 'a.rs:1: bad'; echo 'Checking 2/2' >&2; exit 1"
+                    .to_string(),
+            ],
+            output_filter: output_filter.iter().map(|f| f.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let linter = Linter::from_config(config(&["^Checking "]), &Default::default());
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stdout(), b"a.rs:1: bad\n");
+        assert!(output.stderr().is_empty());
+        assert_eq!(output.parsed().len(), 1);
+        assert!(output.process_failed());
+
+        let linter = Linter::from_config(config(&[]), &Default::default());
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stdout(), b"Checking 1/2\na.rs:1: bad\n");
+
+        let linter = Linter::from_config(config(&["("]), &Default::default());
+        assert!(matches!(
+            linter.check_output_filter(),
+            Err(MultilintError::Config(_))
+        ));
+        assert!(matches!(linter.run(&root), Err(MultilintError::Config(_))));
+    }
+
     #[test]
     fn no_files() {
         let root = tempdir().unwrap();
@@ -287,6 +1988,20 @@ mod tests {
         assert!(output.is_none());
     }
 
+    #[test]
+    fn command_not_found() {
+        let root = tempdir().unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "multilint-command-not-found".to_string(),
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let err = linter.run(&root).unwrap_err();
+        assert!(matches!(err, MultilintError::CommandNotFound(_)));
+    }
+
     #[test]
     fn no_includes() {
         let root = tempdir().unwrap();
@@ -324,6 +2039,55 @@ mod tests {
             .contains("main.rs"));
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn create_work_dir() {
+        let root = tempdir().unwrap();
+        let build = root.path().join("build").join("lint");
+        let mut config = LinterConfig {
+            command: "pwd".to_string(),
+            work_dir: build.clone(),
+            ..Default::default()
+        };
+        let linter = Linter::from_config(config.clone(), &Default::default());
+        assert!(linter.run(&root).is_err());
+        assert!(!build.exists());
+
+        config.create_work_dir = true;
+        let linter = Linter::from_config(config, &Default::default());
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.success());
+        assert!(build.is_dir());
+        assert_eq!(
+            Path::new(std::str::from_utf8(output.stdout()).unwrap().trim_end()),
+            fs::canonicalize(&build).unwrap()
+        );
+    }
+
+    #[test]
+    fn root_placeholder() {
+        let root = tempdir().unwrap();
+        let subdir = root.path().join("sub");
+        create_dir(&subdir).unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                command: "echo".to_string(),
+                options: vec!["--config={root}/config".to_string()],
+                work_dir: subdir,
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(
+            std::str::from_utf8(output.stdout()).unwrap().trim_end(),
+            format!(
+                "--config={}/config",
+                fs::canonicalize(&root).unwrap().display()
+            )
+        );
+    }
+
     #[test]
     fn modified() {
         let root = tempdir().unwrap();
@@ -332,7 +2096,7 @@ mod tests {
             let mut file = File::create(&main).unwrap();
             write!(&mut file, " use std;").unwrap();
         }
-        let linter = Linter::from_config(
+        let mut linter = Linter::from_config(
             LinterConfig {
                 command: "cargo".to_string(),
                 options: vec!["fmt".to_string(), "--".to_string()],
@@ -343,7 +2107,15 @@ mod tests {
         );
         let output = linter.run(&root).unwrap().unwrap();
         assert!(!output.success());
+        assert!(!output.process_failed());
+        assert!(output.has_modifications());
         assert!(read_to_string(&main).unwrap().starts_with("use std;"));
+
+        fs::write(&main, " use std;").unwrap();
+        linter.fix();
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.success());
+        assert!(output.has_modifications());
     }
 
     #[cfg(unix)]
@@ -370,6 +2142,301 @@ mod tests {
         assert!(output.success());
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn hash_bom() {
+        let root = tempdir().unwrap();
+        let main = root.path().join("main.rs");
+        let config = |input_encoding| {
+            LinterConfig {
+            command: "bash".to_string(),
+            options: vec![
+                "-c".to_string(),
+                r#"for f; do printf '\xef\xbb\xbf%s' "$(sed '1s/^\xef\xbb\xbf//' "$f")" > "$f"; done"#
+                    .to_string(),
+                "--".to_string(),
+            ],
+            includes: vec!["*.rs".to_string()],
+            check_hash: true,
+            input_encoding,
+            ..Default::default()
+        }
+        };
+        fs::write(&main, "fn main() {}").unwrap();
+        let linter = Linter::from_config(config(InputEncoding::Raw), &Default::default());
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.has_modifications());
+
+        fs::write(&main, "fn main() {}").unwrap();
+        let linter = Linter::from_config(config(InputEncoding::Auto), &Default::default());
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.success());
+        assert!(fs::read(&main).unwrap().starts_with(b"\xef\xbb\xbf"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hash_algorithms() {
+        let root = tempdir().unwrap();
+        let main = root.path().join("main.rs");
+        File::create(&main).unwrap();
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha1,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Xxh3,
+        ] {
+            let linter = |script: &str| {
+                Linter::from_config(
+                    LinterConfig {
+                        command: "bash".to_string(),
+                        options: vec!["-c".to_string(), script.to_string(), "--".to_string()],
+                        includes: vec!["*.rs".to_string()],
+                        hash_algorithm: Some(algorithm),
+                        ..Default::default()
+                    },
+                    &Default::default(),
+                )
+            };
+            let output = linter("sleep 0.1; touch $@").run(&root).unwrap().unwrap();
+            assert!(output.success(), "{:?}", algorithm);
+            let output = linter("echo >> $1").run(&root).unwrap().unwrap();
+            assert_eq!(output.modified().len(), 1, "{:?}", algorithm);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn post_run() {
+        let root = tempdir().unwrap();
+        let main = root.path().join("main.rs");
+        File::create(&main).unwrap();
+        let linter = |script: &str| {
+            Linter::from_config(
+                LinterConfig {
+                    command: "bash".to_string(),
+                    options: vec!["-c".to_string(), script.to_string(), "--".to_string()],
+                    includes: vec!["*.rs".to_string()],
+                    check_hash: true,
+                    post_run: vec![
+                        "echo".to_string(),
+                        "post".to_string(),
+                        "{modified}".to_string(),
+                    ],
+                    ..Default::default()
+                },
+                &Default::default(),
+            )
+        };
+
+        let output = linter("echo >> $1").run(&root).unwrap().unwrap();
+        assert_eq!(
+            std::str::from_utf8(output.stdout()).unwrap().trim_end(),
+            format!("post {}", main.display())
+        );
+
+        let output = linter("true").run(&root).unwrap().unwrap();
+        assert!(output.stdout().is_empty());
+
+        let mut config = LinterConfig {
+            command: "true".to_string(),
+            includes: vec!["*.rs".to_string()],
+            post_run: vec!["false".to_string()],
+            ..Default::default()
+        };
+        let output = Linter::from_config(config.clone(), &Default::default())
+            .run(&root)
+            .unwrap()
+            .unwrap();
+        assert!(output.process_failed());
+        assert!(std::str::from_utf8(output.stderr())
+            .unwrap()
+            .contains("post_run \"false\" failed"));
+
+        config.post_run = vec!["true".to_string()];
+        let output = Linter::from_config(config, &Default::default())
+            .run(&root)
+            .unwrap()
+            .unwrap();
+        assert!(output.success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_stream() {
+        let root = tempdir().unwrap();
+        let parse = |config: LinterConfig| -> Vec<String> {
+            let output = Linter::from_config(
+                LinterConfig {
+                    command: "bash".to_string(),
+                    options: vec![
+                        "-c".to_string(),
+                        "echo 'a.rs:1: out'; echo 'b.rs(2): err' >&2".to_string(),
+                    ],
+                    ..config
+                },
+                &Default::default(),
+            )
+            .run(&root)
+            .unwrap()
+            .unwrap();
+            output.parsed().iter().map(|p| p.file.clone()).collect()
+        };
+        let stream = |parse_stream| LinterConfig {
+            parse_stream,
+            error_format: vec!["%f:%l: %m".to_string(), "%f(%l): %m".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(parse(stream(ParseStream::Stdout)), vec!["a.rs"]);
+        assert_eq!(parse(stream(ParseStream::Stderr)), vec!["b.rs"]);
+        assert_eq!(parse(stream(ParseStream::Both)), vec!["b.rs", "a.rs"]);
+        // GNU formats by default
+        assert_eq!(parse(Default::default()), vec!["a.rs"]);
+        assert_eq!(
+            parse(LinterConfig {
+                error_format_stderr: vec!["%f(%l): %m".to_string()],
+                ..Default::default()
+            }),
+            vec!["b.rs", "a.rs"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell() {
+        let root = tempdir().unwrap();
+        File::create(root.path().join("a.rs")).unwrap();
+        let linter = Linter::from_config(
+            LinterConfig {
+                shell: Some("echo start && echo files:".to_string()),
+                includes: vec!["*.rs".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        assert_eq!(linter.program(), "sh");
+        assert!(linter.is_executable());
+        let output = linter.run(&root).unwrap().unwrap();
+        let stdout = std::str::from_utf8(output.stdout()).unwrap();
+        assert!(stdout.starts_with("start\nfiles: "), "{}", stdout);
+        assert!(stdout.ends_with("a.rs\n"), "{}", stdout);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn env() {
+        std::env::set_var("MULTILINT_TEST_ENV", "/usr/bin");
+        assert_eq!(expand_env("${MULTILINT_TEST_ENV}:/opt"), "/usr/bin:/opt");
+        assert_eq!(expand_env("a${MULTILINT_TEST_UNSET}b"), "ab");
+        assert_eq!(expand_env("$HOME ${open"), "$HOME ${open");
+
+        let root = tempdir().unwrap();
+        let output = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec!["-c".to_string(), "echo $FLAGS".to_string()],
+                env: [("FLAGS".to_string(), "${MULTILINT_TEST_ENV} -D".to_string())].into(),
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .run(&root)
+        .unwrap()
+        .unwrap();
+        assert_eq!(output.stdout(), b"/usr/bin -D\n");
+
+        // the names of the variables are part of the settings
+        let key = |name: &str| {
+            Linter::from_config(
+                LinterConfig {
+                    command: "true".to_string(),
+                    includes: vec!["*.rs".to_string()],
+                    env: [(name.to_string(), "1".to_string())].into(),
+                    ..Default::default()
+                },
+                &GlobalConfig {
+                    cache: true,
+                    ..Default::default()
+                },
+            )
+            .cache_key()
+        };
+        assert_eq!(key("A"), key("A"));
+        assert_ne!(key("A"), key("B"));
+    }
+
+    #[test]
+    fn decode_bom() {
+        assert_eq!(&*decode(b"abc"), b"abc");
+        assert_eq!(&*decode(b"\xef\xbb\xbfabc"), b"abc");
+        assert_eq!(&*decode(b"\xff\xfea\0b\0"), b"ab");
+        assert_eq!(&*decode(b"\xfe\xff\0a\0b"), b"ab");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn snapshot() {
+        let root = tempdir().unwrap();
+        fs::write(root.path().join("a.rs"), "fn a() {}").unwrap();
+        fs::write(root.path().join("b.rs"), "fn b() {}").unwrap();
+        let mut linter = Linter::from_config(
+            LinterConfig {
+                command: "sh".to_string(),
+                options: vec![
+                    "-c".to_string(),
+                    "for f; do case $f in *a.rs) echo >> \"$f\";; esac; done".to_string(),
+                    "sh".to_string(),
+                ],
+                includes: vec!["*.rs".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+        linter.snapshot();
+        let output = linter.run(&root).unwrap().unwrap();
+        assert_eq!(output.stats().files, 2);
+        // only the contents of the modified files are kept
+        let originals = output.originals();
+        assert_eq!(originals.len(), 1);
+        assert!(originals[0].0.ends_with("a.rs"));
+        assert_eq!(originals[0].1, b"fn a() {}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn integrity() {
+        let root = tempdir().unwrap();
+        File::create(root.path().join("main.rs")).unwrap();
+        File::create(root.path().join("other.txt")).unwrap();
+        let mut linter = Linter::from_config(
+            LinterConfig {
+                command: "bash".to_string(),
+                options: vec![
+                    "-c".to_string(),
+                    r#"sleep 0.1; touch "$(dirname "$1")/other.txt""#.to_string(),
+                    "--".to_string(),
+                ],
+                includes: vec!["*.rs".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+        );
+
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.modified_outside().is_empty());
+
+        linter.check_integrity(false);
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(output.success());
+        assert_eq!(output.modified_outside(), [root.path().join("other.txt")]);
+
+        linter.check_integrity(true);
+        let output = linter.run(&root).unwrap().unwrap();
+        assert!(!output.success());
+        assert!(output.failed());
+        assert!(!output.has_modifications());
+    }
+
     #[test]
     fn submodule() {
         let root = tempdir().unwrap();
@@ -411,5 +2478,10 @@ mod tests {
         };
         test(false, 2);
         test(true, 1);
+
+        // a linked worktree is a checkout of the same repository
+        git(&["worktree", "add", "--detach", "worktree"]);
+        test(false, 3);
+        test(true, 2);
     }
 }
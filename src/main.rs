@@ -1,17 +1,99 @@
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
 use colored::Colorize;
 use log::debug;
-use multilint::{driver, format};
-use std::{env, path::PathBuf, process::exit};
+use multilint::{check, config, doctor, driver, format, git, linter::Linter, patch};
+use serde_json::json;
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsStr,
+    fmt::Display,
+    fs,
+    io::{self, stderr, stdin, stdout, IsTerminal, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::exit,
+    str::FromStr,
+    time::Duration,
+};
 use structopt::clap::arg_enum;
 use structopt::{clap, StructOpt};
 
+mod bench;
+
+/// Exit code when some linter failed
+const FAILURE_EXIT_CODE: i32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Null,
+    Status,
+    Count,
+    Raw,
+    Text,
+    Junit,
+    Sarif,
+    Syslog,
+    Ndjson,
+    GithubActions,
+}
+
+impl Format {
+    /// Values of --format, spelled with dashes that arg_enum! does not support
+    const VALUES: &'static [(&'static str, Format)] = &[
+        ("null", Format::Null),
+        ("status", Format::Status),
+        ("count", Format::Count),
+        ("raw", Format::Raw),
+        ("text", Format::Text),
+        ("junit", Format::Junit),
+        ("sarif", Format::Sarif),
+        ("syslog", Format::Syslog),
+        ("ndjson", Format::Ndjson),
+        ("github-actions", Format::GithubActions),
+    ];
+
+    fn variants() -> Vec<&'static str> {
+        Format::VALUES.iter().map(|&(name, _)| name).collect()
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::VALUES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, format)| format)
+            .ok_or_else(|| format!("valid values: {}", Format::variants().join(", ")))
+    }
+}
+
+arg_enum! {
+    #[derive(Debug)]
+    enum Progress {
+        Auto,
+        Always,
+        Never,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug)]
+    enum FailOnSkip {
+        Command,
+        File,
+        All,
+    }
+}
+
 arg_enum! {
     #[derive(Debug)]
-    enum Format {
-        Null,
-        Raw,
-        Text,
+    enum CiGroups {
+        Auto,
+        Github,
+        Gitlab,
     }
 }
 
@@ -22,36 +104,729 @@ struct Opt {
     #[structopt(short = "C")]
     pub work_dir: Option<PathBuf>,
 
+    /// Loads only this config file instead of searching multilint.toml in the working directory and its parents
+    #[structopt(long = "config", value_name = "PATH")]
+    config_file: Option<PathBuf>,
+
     /// Output format
     #[structopt(short, long, possible_values = &Format::variants(), case_insensitive = true, default_value="text")]
     format: Format,
 
+    /// Writes the report to the file instead of stdout (junit, sarif), or the patch with --patch
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
+
+    /// Indents the JSON document (sarif)
+    #[structopt(long)]
+    pretty: bool,
+
+    /// Prints errors of multilint itself as JSON objects on stdout instead of plain lines on stderr (ndjson)
+    #[structopt(long)]
+    json_errors: bool,
+
+    /// Prints the diagnostics of all linters grouped by file and sorted by position at the end (ndjson)
+    #[structopt(long)]
+    group_by_file: bool,
+
     /// Linters to run
     #[structopt(short, long = "linter")]
     linters: Option<Vec<String>>,
+
+    /// Number of linters run at once, overriding global.jobs
+    #[structopt(short, long)]
+    jobs: Option<NonZeroUsize>,
+
+    /// Number of child processes run at once across all linters [default: twice the number of CPUs]
+    #[structopt(long, value_name = "N")]
+    max_procs: Option<NonZeroUsize>,
+
+    /// Only lints files matching the glob
+    #[structopt(long, number_of_values = 1)]
+    changed_only_within: Vec<String>,
+
+    /// Adds a glob to the includes of all selected linters that have includes
+    #[structopt(long = "include", number_of_values = 1)]
+    includes: Vec<String>,
+
+    /// Adds a glob to the excludes of all selected linters
+    #[structopt(long = "exclude", number_of_values = 1)]
+    excludes: Vec<String>,
+
+    /// Only lints files that git reports as untracked or modified, for every linter
+    #[structopt(long)]
+    uncommitted_only: bool,
+
+    /// Only lints files that differ from the git revision given as --changed=REF, HEAD if omitted, or are untracked
+    #[structopt(long, value_name = "REF", require_equals = true)]
+    changed: Option<Option<String>>,
+
+    /// Only lints files modified after this file, or all files if it does not exist
+    #[structopt(long, value_name = "FILE")]
+    newer_than: Option<PathBuf>,
+
+    /// Sets the modification time of this file to the start of the run if it succeeds, creating it if needed
+    #[structopt(long, value_name = "FILE")]
+    touch: Option<PathBuf>,
+
+    /// Refuses to run if git reports untracked or modified files, so that fixes by formatters are not mixed with other edits
+    #[structopt(long)]
+    require_clean: bool,
+
+    /// Runs every linter one file at a time, which is much slower but helps to find the file breaking a linter
+    #[structopt(long)]
+    single_file: bool,
+
+    /// Hides the output of passing linters (text)
+    #[structopt(long)]
+    hide_passing_output: bool,
+
+    /// Prints at most N lines of output per linter, writing the full output to a temporary file (text)
+    #[structopt(long, value_name = "N")]
+    inline_lines: Option<usize>,
+
+    /// Prints a summary of the diagnostics parsed from the output after it (text)
+    #[structopt(long)]
+    tee: bool,
+
+    /// Prints the number of parsed diagnostics instead of the output, which is still printed if nothing is parsed (text)
+    #[structopt(long)]
+    count_problems: bool,
+
+    /// Prints the working directory of each linter (text)
+    #[structopt(short, long)]
+    verbose: bool,
+
+    /// Warns when a linter modifies files it was not given, comparing the mtime of every file in the tree before and after each linter
+    #[structopt(long)]
+    check_integrity: bool,
+
+    /// Fails when a linter modifies files it was not given (implies --check-integrity)
+    #[structopt(long)]
+    strict: bool,
+
+    /// Continues with the other linters when a linter cannot be run
+    #[structopt(short, long)]
+    keep_going: bool,
+
+    /// Prints the status, time and file counts of each linter, as a table in text format or JSON on stderr otherwise
+    #[structopt(long)]
+    stats: bool,
+
+    /// Fails when linters are skipped because the command is not found (command), no files matched (file), or either (all)
+    #[structopt(long, possible_values = &FailOnSkip::variants(), case_insensitive = true)]
+    fail_on_skip: Option<FailOnSkip>,
+
+    /// Prints the install_hint of linters whose command is not found
+    #[structopt(long)]
+    show_install_hints: bool,
+
+    /// Rebuilds the file index from scratch, enabling it for this run
+    #[structopt(long, conflicts_with = "no-index")]
+    reindex: bool,
+
+    /// Walks the tree instead of using the file index
+    #[structopt(long)]
+    no_index: bool,
+
+    /// Validates the config without running linters and exits nonzero on problems
+    #[structopt(long)]
+    check_config: bool,
+
+    /// Prints a digest of the merged config, or of each selected linter with --linter, and exits
+    #[structopt(long)]
+    config_digest: bool,
+
+    /// Prints the name and command of each selected linter, and whether the command is found, and exits
+    #[structopt(long)]
+    list: bool,
+
+    /// Prints each tag of the selected linters with the linters carrying it, and exits (as JSON lines with --format ndjson)
+    #[structopt(long)]
+    list_tags: bool,
+
+    /// Warns when listing the files of a linter takes longer than this, such as "500ms"
+    #[structopt(long, default_value = "3s", parse(try_from_str = humantime::parse_duration))]
+    max_walk_time: Duration,
+
+    /// Exits with the exit code of the linter, which must be the only one selected
+    #[structopt(long)]
+    propagate_exit: bool,
+
+    /// Signal sent to linters exceeding their timeout before killing them, such as TERM (unix only)
+    #[structopt(long, value_name = "SIGNAL")]
+    linter_timeout_kill_signal: Option<String>,
+
+    /// Links the locations listed by --tee to the files with OSC 8 hyperlinks when printing to a terminal
+    #[structopt(long)]
+    hyperlinks: bool,
+
+    /// Shows the output of the linters as it is written, running them one at a time (text format only)
+    #[structopt(long)]
+    no_capture: bool,
+
+    /// Prefixes each line shown by --no-capture with `[linter]`, allowing linters to run with --jobs
+    #[structopt(long)]
+    prefix_lines: bool,
+
+    /// Exit code used when linters only modified files [default: the failure exit code, 1]
+    #[structopt(long)]
+    modified_exit_code: Option<i32>,
+
+    /// With --fix, stages again the files that formatters modified if they were staged
+    #[structopt(long)]
+    staged: bool,
+
+    /// Prints the command lines each selected linter would run, batch by batch, without running them
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Stops after the first linter that fails instead of running all of them
+    #[structopt(long)]
+    fail_fast: bool,
+
+    /// Writes the changes of the formatters as a unified diff and reverts the files
+    #[structopt(long)]
+    patch: bool,
+
+    /// Counts files modified by formatters as fixed rather than failed, and prints how many each rewrote
+    #[structopt(long)]
+    fix: bool,
+
+    /// Wraps the output of each linter in collapsible CI log sections
+    #[structopt(long, possible_values = &CiGroups::variants(), case_insensitive = true)]
+    ci_groups: Option<CiGroups>,
+
+    /// Prints progress and a summary to stderr (auto: when stderr is a terminal and the format is not text)
+    #[structopt(long, possible_values = &Progress::variants(), case_insensitive = true, default_value = "auto")]
+    progress: Progress,
+
+    /// Lints only these files instead of walking the tree, or the files listed on stdin if `-`
+    #[structopt(name = "FILES")]
+    files: Vec<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-fn run() -> Result<()> {
-    let opt = Opt::from_args();
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Checks whether the configured linters can be run
+    Doctor,
+
+    /// Runs a no-op linter over a generated tree and reports timings
+    #[structopt(setting = clap::AppSettings::Hidden)]
+    Bench {
+        /// Number of files to generate
+        #[structopt(long, default_value = "10000")]
+        files: usize,
+    },
+}
+
+fn run(mut opt: Opt) -> Result<()> {
+    let json_errors = opt.json_errors && matches!(opt.format, Format::Ndjson);
+    // the config path is relative to where multilint was started, not to -C
+    if let Some(config_file) = &mut opt.config_file {
+        *config_file = env::current_dir()?.join(&*config_file);
+    }
+    // so are the given files, which the linters take relative to the new working directory
+    let files = given_files(&opt.files)?;
     if let Some(work_dir) = &opt.work_dir {
         debug!("change CWD: {}", work_dir.display());
         env::set_current_dir(work_dir)?;
     }
-    let format: Box<dyn format::OutputFormat> = match opt.format {
+    let cwd = env::current_dir()?;
+    let files = files
+        .into_iter()
+        .map(|file| {
+            file.strip_prefix(&cwd)
+                .map(Path::to_path_buf)
+                .unwrap_or(file)
+        })
+        .collect();
+    let ci_groups = match opt.ci_groups {
+        None => None,
+        Some(CiGroups::Auto) => format::CiGroups::detect(),
+        Some(CiGroups::Github) => Some(format::CiGroups::Github),
+        Some(CiGroups::Gitlab) => Some(format::CiGroups::Gitlab),
+    };
+    match opt.command {
+        Some(Command::Doctor) => {
+            return run_doctor(opt.config_file.as_deref(), opt.linters.as_deref())
+        }
+        Some(Command::Bench { files }) => {
+            let report = bench::bench(files)?;
+            println!("files:   {}", report.files);
+            println!("batches: {}", report.batches);
+            println!("walk:    {:?}", report.walk);
+            println!("total:   {:?}", report.total);
+            return Ok(());
+        }
+        None => {}
+    }
+    if opt.check_config {
+        return run_check_config(
+            opt.config_file.as_deref(),
+            matches!(opt.format, Format::Ndjson),
+        );
+    }
+    if opt.config_digest {
+        return print_config_digest(opt.config_file.as_deref(), opt.linters.as_deref());
+    }
+    if opt.list {
+        return print_linters(opt.config_file.as_deref(), opt.linters.as_deref());
+    }
+    if opt.list_tags {
+        return print_tags(
+            opt.config_file.as_deref(),
+            opt.linters.as_deref(),
+            matches!(opt.format, Format::Ndjson),
+        );
+    }
+    let text = matches!(opt.format, Format::Text);
+    ensure!(
+        text || !opt.no_capture,
+        "--no-capture requires --format text"
+    );
+    ensure!(opt.fix || !opt.staged, "--staged requires --fix");
+    ensure!(
+        !(opt.staged && opt.patch),
+        "--staged cannot be used with --patch, which reverts the files"
+    );
+    ensure!(
+        opt.no_capture || !opt.prefix_lines,
+        "--prefix-lines requires --no-capture"
+    );
+    if opt.patch {
+        match opt.output {
+            Some(_) => ensure!(
+                !matches!(opt.format, Format::Junit | Format::Sarif),
+                "--patch cannot share --output with the junit or sarif report"
+            ),
+            None => ensure!(
+                matches!(opt.format, Format::Null),
+                "--patch without --output requires --format null"
+            ),
+        }
+    }
+    let mut format: Box<dyn format::OutputFormat> = match opt.format {
         Format::Null => Box::<format::NullFormat>::default(),
-        Format::Raw => Box::<format::RawFormat>::default(),
-        Format::Text => Box::<format::TextFormat>::default(),
+        Format::Status => Box::<format::StatusFormat>::default(),
+        Format::Count => Box::<format::CountFormat>::default(),
+        Format::Raw => Box::new(format::RawFormat { ci_groups }),
+        Format::Text => Box::new(format::TextFormat {
+            ci_groups,
+            hide_passing_output: opt.hide_passing_output,
+            tee: opt.tee,
+            verbose: opt.verbose,
+            inline_lines: opt.inline_lines,
+            count_problems: opt.count_problems,
+            streamed: opt.no_capture,
+            hyperlinks: opt.hyperlinks && stdout().is_terminal(),
+            ..Default::default()
+        }),
+        Format::Junit => Box::new(format::JunitFormat::new(opt.output.clone())),
+        Format::Sarif => Box::new(format::SarifFormat::new(opt.output.clone(), opt.pretty)),
+        Format::Syslog => syslog_format()?,
+        Format::Ndjson if opt.group_by_file => Box::new(format::NdjsonFormat::group_by_file()),
+        Format::Ndjson => Box::<format::NdjsonFormat>::default(),
+        Format::GithubActions => Box::new(format::GithubFormat::from_env()),
+    };
+    let progress = match opt.progress {
+        Progress::Auto => {
+            !text && !matches!(opt.format, Format::Status | Format::Count) && stderr().is_terminal()
+        }
+        Progress::Always => true,
+        Progress::Never => false,
+    };
+    if progress {
+        format = Box::new(format::ProgressFormat::new(format));
+    }
+    let options = driver::Options {
+        config_file: opt.config_file.clone(),
+        files,
+        changed_only_within: opt.changed_only_within,
+        includes: opt.includes,
+        excludes: opt.excludes,
+        uncommitted_only: opt.uncommitted_only,
+        changed: opt
+            .changed
+            .map(|rev| rev.unwrap_or_else(|| "HEAD".to_string())),
+        newer_than: opt.newer_than,
+        touch: opt.touch,
+        require_clean: opt.require_clean,
+        single_file: opt.single_file,
+        check_integrity: opt.check_integrity || opt.strict,
+        strict: opt.strict,
+        fix: opt.fix,
+        allow_modified: opt.modified_exit_code == Some(0),
+        keep_going: opt.keep_going,
+        fail_on_no_command: matches!(
+            opt.fail_on_skip,
+            Some(FailOnSkip::Command | FailOnSkip::All)
+        ),
+        show_install_hints: opt.show_install_hints,
+        fail_on_no_file: matches!(opt.fail_on_skip, Some(FailOnSkip::File | FailOnSkip::All)),
+        reindex: opt.reindex,
+        no_index: opt.no_index,
+        max_walk_time: Some(opt.max_walk_time),
+        jobs: opt.jobs.map(NonZeroUsize::get),
+        max_procs: opt.max_procs.map(NonZeroUsize::get),
+        kill_signal: opt.linter_timeout_kill_signal.clone(),
+        no_capture: opt.no_capture,
+        prefix_lines: opt.prefix_lines,
+        patch: opt.patch,
+        fail_fast: opt.fail_fast,
+    };
+    if opt.dry_run {
+        let plans = driver::dry_run(env::current_dir()?, opt.linters.as_deref(), &options)?;
+        print_plans(&plans);
+        return Ok(());
+    }
+    if opt.propagate_exit {
+        let selected = driver::selected_linters(
+            env::current_dir()?,
+            opt.config_file.as_deref(),
+            opt.linters.as_deref(),
+        )?;
+        ensure!(
+            selected.len() == 1,
+            "--propagate-exit requires exactly one linter, but {} are selected",
+            selected.len()
+        );
+    }
+    // staging the fixes of files with unstaged changes would also stage those changes
+    let unstaged = match opt.staged {
+        true => git::unstaged(".").unwrap_or_default(),
+        false => HashSet::new(),
+    };
+    let report = driver::run_linters(
+        env::current_dir()?,
+        &*format,
+        None,
+        opt.linters.as_deref(),
+        &options,
+    )?;
+    if opt.stats {
+        print_stats(&report.results, text)?;
+    }
+    if opt.staged && !report.modified_files.is_empty() {
+        let (partial, files): (Vec<PathBuf>, Vec<PathBuf>) =
+            report.modified_files.iter().cloned().partition(|file| {
+                fs::canonicalize(file).is_ok_and(|file| unstaged.contains(&file))
+            });
+        for file in &partial {
+            print_warning(&format!(
+                "not staging the fixes of \"{}\", which has unstaged changes",
+                file.display()
+            ));
+        }
+        match git::restage(".", &files)? {
+            Some(files) => debug!("restaged: {:?}", files),
+            None => debug!("not restaging: not in a git work tree"),
+        }
+    }
+    if opt.patch {
+        let patch = patch::revert(&report.originals)?;
+        match &opt.output {
+            Some(path) => fs::write(path, patch)
+                .with_context(|| format!("Cannot write \"{}\"", path.display()))?,
+            None => stdout().write_all(patch.as_bytes())?,
+        }
+    }
+    if !report.errors.is_empty() {
+        // the ndjson format already has a record of each error of a linter
+        if !json_errors {
+            for (name, e) in &report.errors {
+                print_error(false, Some(name), e);
+            }
+        }
+        exit(2);
+    }
+    if opt.propagate_exit {
+        if let Some((_, code)) = report.exit_codes.first() {
+            exit(code.unwrap_or(FAILURE_EXIT_CODE));
+        }
+    }
+    if report.failed || !report.skipped.is_empty() {
+        exit(FAILURE_EXIT_CODE);
+    }
+    if report.modified {
+        exit(opt.modified_exit_code.unwrap_or(FAILURE_EXIT_CODE));
+    }
+    Ok(())
+}
+
+/// Reads the files from stdin if the only argument is `-`, and makes them absolute
+fn given_files(files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let files = match files {
+        [file] if file.as_os_str() == "-" => stdin()
+            .lines()
+            .map(|line| line.map(PathBuf::from))
+            .filter(|line| !matches!(line, Ok(path) if path.as_os_str().is_empty()))
+            .collect::<io::Result<_>>()?,
+        files => files.to_vec(),
+    };
+    let cwd = env::current_dir()?;
+    Ok(files.into_iter().map(|file| cwd.join(file)).collect())
+}
+
+#[cfg(unix)]
+fn syslog_format() -> Result<Box<dyn format::OutputFormat>> {
+    Ok(Box::new(format::SyslogFormat::connect()?))
+}
+
+#[cfg(not(unix))]
+fn syslog_format() -> Result<Box<dyn format::OutputFormat>> {
+    anyhow::bail!("--format syslog is only supported on unix")
+}
+
+fn run_doctor(config_file: Option<&Path>, linters: Option<&[String]>) -> Result<()> {
+    let mut critical = false;
+    for (name, checks) in doctor::diagnose(env::current_dir()?, config_file, linters)? {
+        println!("{}", name.bold());
+        for check in checks {
+            match &check.problem {
+                None => println!("  {} {}", "ok".green(), check.description),
+                Some(problem) => {
+                    let status = if check.critical {
+                        "failed".red()
+                    } else {
+                        "warning".yellow()
+                    };
+                    println!("  {} {}: {}", status, check.description, problem);
+                    critical |= check.critical;
+                }
+            }
+        }
+    }
+    if critical {
+        exit(FAILURE_EXIT_CODE);
+    }
+    Ok(())
+}
+
+fn run_check_config(config_file: Option<&Path>, json: bool) -> Result<()> {
+    let problems = check::check_config(env::current_dir()?, config_file);
+    for problem in &problems {
+        if json {
+            println!("{}", serde_json::to_string(problem)?);
+        } else {
+            match &problem.linter {
+                Some(linter) => println!("{}: {}", linter.bold(), problem.message),
+                None => println!("{}", problem.message),
+            }
+        }
+    }
+    if !problems.is_empty() {
+        exit(FAILURE_EXIT_CODE);
+    }
+    if !json {
+        println!("{}", "config ok".green());
+    }
+    Ok(())
+}
+
+fn print_linters(config_file: Option<&Path>, linters: Option<&[String]>) -> Result<()> {
+    let selected = driver::selected_linters(env::current_dir()?, config_file, linters)?;
+    let width = |f: fn(&(String, config::LinterConfig)) -> usize| {
+        selected.iter().map(f).max().unwrap_or_default()
     };
-    if !driver::run_linters(env::current_dir()?, &*format, opt.linters.as_deref())? {
-        exit(1);
+    let name_width = width(|(name, _)| name.len());
+    // linters with `shell` show the command line
+    let command_width = width(|(_, linter_config)| {
+        let command = &linter_config.command;
+        linter_config.shell.as_ref().unwrap_or(command).len()
+    });
+    for (name, linter_config) in selected {
+        let command = linter_config
+            .shell
+            .clone()
+            .unwrap_or_else(|| linter_config.command.clone());
+        // the global settings do not affect finding the command
+        let found = Linter::from_config(linter_config, &Default::default()).is_executable();
+        println!(
+            "{:name_width$}  {:command_width$}  {}",
+            name,
+            command,
+            if found { "found" } else { "not found" }
+        );
+    }
+    Ok(())
+}
+
+fn print_plans(plans: &[(String, driver::Plan)]) {
+    for (name, plan) in plans {
+        match plan {
+            driver::Plan::Commands(commands, work_dir) => {
+                if work_dir == Path::new(".") {
+                    println!("{}:", name.bold());
+                } else {
+                    println!("{}: in {}", name.bold(), work_dir.display());
+                }
+                for argv in commands {
+                    let argv: Vec<String> = argv.iter().map(|arg| quote(arg)).collect();
+                    println!("  {}", argv.join(" "));
+                }
+            }
+            driver::Plan::NoFile => println!("{}: {}", name.bold(), "no file".yellow()),
+            driver::Plan::NoCommand => println!("{}: {}", name.bold(), "no command".yellow()),
+            driver::Plan::Alternative(selected) => {
+                println!("{}: {} {}", name.bold(), "skipped for".yellow(), selected)
+            }
+        }
+    }
+}
+
+/// Quotes an argument for a POSIX shell if it has characters the shell would interpret
+fn quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        return arg.into_owned();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+fn print_tags(config_file: Option<&Path>, linters: Option<&[String]>, json: bool) -> Result<()> {
+    let selected = driver::selected_linters(env::current_dir()?, config_file, linters)?;
+    let tags = driver::tags(&selected);
+    let width = tags.keys().map(String::len).max().unwrap_or_default();
+    for (tag, names) in tags {
+        if json {
+            println!("{}", json!({ "tag": tag, "linters": names }));
+        } else {
+            println!("{:width$}  {}", tag, names.join(", "));
+        }
+    }
+    Ok(())
+}
+
+fn print_config_digest(config_file: Option<&Path>, linters: Option<&[String]>) -> Result<()> {
+    match linters {
+        Some(linters) => {
+            for (name, linter_config) in
+                driver::selected_linters(env::current_dir()?, config_file, Some(linters))?
+            {
+                println!("{}  {}", linter_config.digest, name);
+            }
+        }
+        None => println!(
+            "{}",
+            config::from_file_or_path(config_file, env::current_dir()?)?.digest
+        ),
+    }
+    Ok(())
+}
+
+fn print_stats(results: &[(String, driver::LinterResult)], text: bool) -> Result<()> {
+    // on stderr, so that the JSON does not corrupt the report on stdout
+    if !text {
+        let stats: serde_json::Map<_, _> = results
+            .iter()
+            .map(|(name, r)| {
+                let mut value = serde_json::to_value(&r.stats)?;
+                value["status"] = json!(r.status);
+                value["duration"] = json!(r.duration.as_secs_f64());
+                Ok((name.clone(), value))
+            })
+            .collect::<Result<_>>()?;
+        eprintln!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    let width = results
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain(["linter".len()])
+        .max()
+        .unwrap_or_default();
+    println!(
+        "{:width$} {:10} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "linter".bold(),
+        "status".bold(),
+        "time".bold(),
+        "matched".bold(),
+        "files".bold(),
+        "batches".bold(),
+        "modified".bold(),
+        "passes".bold(),
+    );
+    for (name, r) in results {
+        let s = &r.stats;
+        println!(
+            "{:width$} {:10} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            name,
+            r.status,
+            format!("{:.2}s", r.duration.as_secs_f64()),
+            s.matched,
+            s.files,
+            s.batches,
+            s.modified,
+            s.passes
+        );
     }
     Ok(())
 }
 
+/// Prints an error of multilint, as a JSON object on stdout to keep the stream parseable if `json`
+fn print_error(json: bool, linter: Option<&str>, error: &dyn Display) {
+    let message = format!("{:#}", error);
+    if json {
+        let mut object = json!({ "type": "error", "message": message });
+        if let Some(linter) = linter {
+            object["linter"] = json!(linter);
+        }
+        println!("{}", object);
+    } else if let Some(linter) = linter {
+        eprintln!("{} {}: {}", "error:".red().bold(), linter, message);
+    } else {
+        eprintln!("{} {}", "error:".red().bold(), message);
+    }
+}
+
+/// Prints a warning of multilint on stderr
+fn print_warning(message: &str) {
+    eprintln!("{} {}", "warning:".yellow().bold(), message);
+}
+
 fn main() {
     env_logger::init();
-    if let Err(e) = run() {
-        eprintln!("{} {:#}", "error:".red().bold(), e);
+    let opt = Opt::from_args();
+    let json_errors = opt.json_errors && matches!(opt.format, Format::Ndjson);
+    if let Err(e) = run(opt) {
+        print_error(json_errors, None, &e);
         exit(2);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Format, Opt};
+    use std::path::Path;
+    use structopt::StructOpt;
+    use test_log::test;
+
+    #[test]
+    fn formats() {
+        let parse = |format: &str| Opt::from_iter_safe(["multilint", "--format", format]);
+        for &(name, _) in Format::VALUES {
+            assert!(parse(name).is_ok(), "{}", name);
+        }
+        assert!(matches!(parse("syslog").unwrap().format, Format::Syslog));
+        assert!(matches!(
+            parse("GitHub-Actions").unwrap().format,
+            Format::GithubActions
+        ));
+        assert!(parse("xml").is_err());
+    }
+
+    #[test]
+    fn changed() {
+        let opt = Opt::from_iter_safe(["multilint", "--changed", "a.rs"]).unwrap();
+        assert_eq!(opt.changed, Some(None));
+        assert_eq!(opt.files, [Path::new("a.rs")]);
+
+        let opt = Opt::from_iter_safe(["multilint", "--changed=main", "a.rs"]).unwrap();
+        assert_eq!(opt.changed, Some(Some("main".to_string())));
+        assert_eq!(opt.files, [Path::new("a.rs")]);
+    }
+}
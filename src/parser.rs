@@ -0,0 +1,445 @@
+use crate::{config::JsonFields, linter::decode};
+use anyhow::{bail, Result};
+use log::debug;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Error formats of GNU-style messages, as emitted by most compilers
+pub const GNU_FORMATS: &[&str] = &["%f:%l:%c: %m", "%f:%l: %m"];
+
+/// Severities recognized at the start of a message, such as `warning: unused variable`
+const SEVERITIES: &[&str] = &["error", "fatal error", "warning", "note", "info", "help"];
+
+/// A diagnostic extracted from linter output
+///
+/// It is only built by `Parser`, so that fields can be added without breaking users.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Parsed {
+    pub file: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+
+    /// End of the span, for linters reporting ranges
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<usize>,
+
+    pub message: String,
+
+    /// Severity such as `error` or `warning` in lowercase, from `%s` or the start of the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+
+    /// Notes following the diagnostic, such as related locations
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub related: Vec<Parsed>,
+}
+
+impl Parsed {
+    /// Returns `file:line:column`, omitting missing parts
+    pub fn location(&self) -> String {
+        let mut location = self.file.clone();
+        for n in [self.line, self.column].into_iter().flatten() {
+            location.push_str(&format!(":{}", n));
+        }
+        location
+    }
+}
+
+/// Converts an error format to a regex matching a whole line
+///
+/// `%f` matches a file name, `%l` a line number, `%c` a column number, `%L` and `%C` the line and
+/// column where the span ends, `%s` a severity, `%m` a message, and `%%` a literal `%`. Other
+/// characters match themselves.
+pub fn to_re(format: &str) -> Result<Regex> {
+    let mut re = String::from("(?m)^");
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+            continue;
+        }
+        match chars.next() {
+            Some('f') => re.push_str(r"(?P<file>[^\n]+?)"),
+            Some('l') => re.push_str(r"(?P<line>\d+)"),
+            Some('c') => re.push_str(r"(?P<column>\d+)"),
+            Some('L') => re.push_str(r"(?P<end_line>\d+)"),
+            Some('C') => re.push_str(r"(?P<end_column>\d+)"),
+            Some('s') => re.push_str(r"(?P<severity>[A-Za-z][A-Za-z ]*?)"),
+            Some('m') => re.push_str(r"(?P<message>[^\n]*?)"),
+            Some('%') => re.push('%'),
+            Some(c) => bail!("Unknown specifier %{} in \"{}\"", c, format),
+            None => bail!("Trailing % in \"{}\"", format),
+        }
+    }
+    re.push_str(r"\r?$");
+    Ok(Regex::new(&re)?)
+}
+
+/// Extracts diagnostics from linter output with error formats
+#[derive(Debug, Clone)]
+pub struct Parser {
+    res: Vec<Regex>,
+    notes: Vec<Regex>,
+}
+
+impl Parser {
+    pub fn new<I, S>(formats: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let res = formats
+            .into_iter()
+            .map(|f| to_re(f.as_ref()))
+            .collect::<Result<_>>()?;
+        Ok(Self { res, notes: vec![] })
+    }
+
+    /// Attaches matches of the note formats to the preceding diagnostic
+    ///
+    /// Note formats take precedence over the error formats for the same text.
+    pub fn notes<I, S>(mut self, formats: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.notes = formats
+            .into_iter()
+            .map(|f| to_re(f.as_ref()))
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    /// Returns diagnostics in order of appearance
+    ///
+    /// When several formats match the same text, the first one wins.
+    pub fn parse(&self, text: &str) -> Vec<Parsed> {
+        let text = strip_ansi(text);
+        let mut matches = BTreeMap::new();
+        let res = self.notes.iter().map(|re| (re, true));
+        for (re, is_note) in res.chain(self.res.iter().map(|re| (re, false))) {
+            for caps in re.captures_iter(&text) {
+                let m = caps.get(0).unwrap();
+                let file = match caps.name("file") {
+                    Some(file) => file.as_str().to_string(),
+                    None => continue,
+                };
+                matches.entry((m.start(), m.end())).or_insert_with(|| {
+                    let message = caps
+                        .name("message")
+                        .map(|m| m.as_str().to_string())
+                        .unwrap_or_default();
+                    let severity = match caps.name("severity") {
+                        Some(s) => Some(s.as_str().to_ascii_lowercase()),
+                        None => severity(&message),
+                    };
+                    let number = |name| caps.name(name).and_then(|n| n.as_str().parse().ok());
+                    let parsed = Parsed {
+                        file,
+                        line: number("line"),
+                        column: number("column"),
+                        end_line: number("end_line"),
+                        end_column: number("end_column"),
+                        message,
+                        severity,
+                        related: vec![],
+                    };
+                    (parsed, is_note)
+                });
+            }
+        }
+
+        let mut parsed: Vec<Parsed> = Vec::new();
+        for (p, is_note) in matches.into_values() {
+            if !is_note {
+                parsed.push(p);
+            } else if let Some(last) = parsed.last_mut() {
+                last.related.push(p);
+            } else {
+                debug!("ignoring note without diagnostic: {}", p.location());
+            }
+        }
+        parsed
+    }
+
+    /// Parses raw output, honoring a byte order mark
+    pub fn parse_bytes(&self, bytes: &[u8]) -> Vec<Parsed> {
+        self.parse(&String::from_utf8_lossy(&decode(bytes)))
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new(GNU_FORMATS).unwrap()
+    }
+}
+
+/// Extracts diagnostics from JSON output, given as a document or as one value per line
+///
+/// Arrays are flattened, and values without a file are skipped, such as the build messages of
+/// `cargo clippy --message-format=json`. Lines that are not JSON are ignored.
+#[derive(Debug, Clone)]
+pub struct JsonParser {
+    fields: JsonFields,
+}
+
+impl JsonParser {
+    pub fn new(fields: JsonFields) -> Self {
+        Self { fields }
+    }
+
+    /// Returns diagnostics in order of appearance
+    pub fn parse(&self, text: &str) -> Vec<Parsed> {
+        let values: Vec<Value> = match serde_json::from_str(text) {
+            Ok(value) => vec![value],
+            Err(_) => text
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+        };
+        let mut parsed = Vec::new();
+        for value in values {
+            match value {
+                Value::Array(values) => {
+                    parsed.extend(values.iter().filter_map(|v| self.diagnostic(v)))
+                }
+                value => parsed.extend(self.diagnostic(&value)),
+            }
+        }
+        parsed
+    }
+
+    /// Parses raw output, honoring a byte order mark
+    pub fn parse_bytes(&self, bytes: &[u8]) -> Vec<Parsed> {
+        self.parse(&String::from_utf8_lossy(&decode(bytes)))
+    }
+
+    fn diagnostic(&self, value: &Value) -> Option<Parsed> {
+        let get = |pointer: &str| match pointer {
+            "" => None,
+            pointer => value.pointer(pointer),
+        };
+        let number = |pointer: &str| {
+            let value = get(pointer)?;
+            match value.as_u64() {
+                Some(n) => usize::try_from(n).ok(),
+                None => value.as_str()?.parse().ok(),
+            }
+        };
+        let text = |value: &Value| match value {
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        };
+
+        let file = get(&self.fields.file)?.as_str()?.to_string();
+        let message = get(&self.fields.message).map(text).unwrap_or_default();
+        let severity = match get(&self.fields.severity) {
+            Some(s) => Some(text(s).to_ascii_lowercase()),
+            None => severity(&message),
+        };
+        Some(Parsed {
+            file,
+            line: number(&self.fields.line),
+            column: number(&self.fields.column),
+            end_line: number(&self.fields.end_line),
+            end_column: number(&self.fields.end_column),
+            message,
+            severity,
+            related: vec![],
+        })
+    }
+}
+
+/// Parser of an output stream, as selected by the `output` of the linter
+#[derive(Debug, Clone)]
+pub enum StreamParser {
+    Text(Parser),
+    Json(JsonParser),
+}
+
+impl StreamParser {
+    pub fn parse_bytes(&self, bytes: &[u8]) -> Vec<Parsed> {
+        match self {
+            StreamParser::Text(parser) => parser.parse_bytes(bytes),
+            StreamParser::Json(parser) => parser.parse_bytes(bytes),
+        }
+    }
+}
+
+/// Returns the severity a message starts with, such as `warning` of `warning: unused variable`
+fn severity(message: &str) -> Option<String> {
+    let (prefix, _) = message.split_once(':')?;
+    let prefix = prefix.trim().to_ascii_lowercase();
+    SEVERITIES.contains(&prefix.as_str()).then_some(prefix)
+}
+
+// removes color codes, which break matching
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+            continue;
+        }
+        stripped.push(c);
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_re, JsonParser, Parsed, Parser};
+    use crate::config::JsonFields;
+    use test_log::test;
+
+    #[test]
+    fn re() {
+        let re = to_re("%f:%l:%c: %m").unwrap();
+        let caps = re.captures("src/main.rs:1:2: error: x").unwrap();
+        assert_eq!(&caps["file"], "src/main.rs");
+        assert_eq!(&caps["line"], "1");
+        assert_eq!(&caps["column"], "2");
+        assert_eq!(&caps["message"], "error: x");
+
+        assert!(to_re("100%% %m").unwrap().is_match("100% done"));
+        assert!(to_re("(%f)").unwrap().is_match("(a)"));
+        assert!(to_re("%x").is_err());
+        assert!(to_re("%").is_err());
+    }
+
+    #[test]
+    fn parse() {
+        let parser = Parser::new(["%f:%l:%c: %m", "%f:%l: %m"]).unwrap();
+        let parsed = parser.parse("a.rs:1:2: first\nnoise\n\x1b[1mb.rs\x1b[0m:3: second\n");
+        assert_eq!(
+            parsed,
+            vec![
+                Parsed {
+                    file: "a.rs".to_string(),
+                    line: Some(1),
+                    column: Some(2),
+                    end_line: None,
+                    end_column: None,
+                    message: "first".to_string(),
+                    severity: None,
+                    related: vec![],
+                },
+                Parsed {
+                    file: "b.rs".to_string(),
+                    line: Some(3),
+                    column: None,
+                    end_line: None,
+                    end_column: None,
+                    message: "second".to_string(),
+                    severity: None,
+                    related: vec![],
+                },
+            ]
+        );
+        assert_eq!(parsed[0].location(), "a.rs:1:2");
+        assert_eq!(parsed[1].location(), "b.rs:3");
+
+        let parsed = Parser::default().parse_bytes(b"\xef\xbb\xbfc.rs:4:5: third");
+        assert_eq!(parsed[0].location(), "c.rs:4:5");
+    }
+
+    #[test]
+    fn severities() {
+        let severities = |parser: &Parser, text: &str| -> Vec<Option<String>> {
+            parser.parse(text).into_iter().map(|p| p.severity).collect()
+        };
+        let text = "a.rs:1:2: warning: unused\nb.rs:3: Fatal Error: x\nc.rs:4: SC2086: quote\n";
+        assert_eq!(
+            severities(&Parser::default(), text),
+            vec![
+                Some("warning".to_string()),
+                Some("fatal error".to_string()),
+                None
+            ]
+        );
+
+        let parser = Parser::new(["%f:%l: [%s] %m"]).unwrap();
+        let parsed = parser.parse("a.rs:1: [Convention] missing docstring\n");
+        assert_eq!(parsed[0].severity.as_deref(), Some("convention"));
+        assert_eq!(parsed[0].message, "missing docstring");
+        assert!(to_re("%f: %s: %m")
+            .unwrap()
+            .is_match("a.rs: fatal error: x"));
+    }
+
+    #[test]
+    fn spans() {
+        let parser = Parser::new(["%f:%l:%c-%L:%C: %m", "%f:%l:%c: %m"]).unwrap();
+        let parsed = parser.parse("a.rs:1:2-3:4: span\nb.rs:5:6: point\n");
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            (parsed[0].end_line, parsed[0].end_column),
+            (Some(3), Some(4))
+        );
+        assert_eq!(parsed[0].message, "span");
+        assert_eq!((parsed[1].end_line, parsed[1].end_column), (None, None));
+    }
+
+    #[test]
+    fn json() {
+        let parser = JsonParser::new(JsonFields {
+            file: "/filename".to_string(),
+            line: "/location/row".to_string(),
+            column: "/location/column".to_string(),
+            end_line: "/end_location/row".to_string(),
+            end_column: "".to_string(),
+            message: "/message".to_string(),
+            severity: "".to_string(),
+        });
+        let parsed = parser.parse(
+            r#"[
+                {"filename": "a.py", "location": {"row": 1, "column": 2}, "end_location": {"row": 3}, "message": "unused"},
+                {"filename": "b.py", "location": {"row": "4"}, "message": "warning: x"},
+                {"message": "no file"}
+            ]"#,
+        );
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].location(), "a.py:1:2");
+        assert_eq!(parsed[0].end_line, Some(3));
+        assert_eq!(parsed[0].end_column, None);
+        assert_eq!(parsed[0].message, "unused");
+        assert_eq!(parsed[0].severity, None);
+        assert_eq!(parsed[1].location(), "b.py:4");
+        assert_eq!(parsed[1].severity.as_deref(), Some("warning"));
+
+        // JSON lines with the default fields, skipping other lines
+        let parsed = JsonParser::new(JsonFields::default()).parse(
+            "Compiling\n{\"file\": \"c.rs\", \"line\": 5, \"message\": \"bad\", \"severity\": \"Error\"}\n{\"reason\": \"done\"}\n",
+        );
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].location(), "c.rs:5");
+        assert_eq!(parsed[0].severity.as_deref(), Some("error"));
+    }
+
+    #[test]
+    fn notes() {
+        let parser = Parser::default().notes(["%f:%l: note: %m"]).unwrap();
+        let parsed = parser.parse(
+            "a.rs:9: note: orphan\na.rs:1:2: error\nb.rs:3: note: first\nc.rs:4: note: second\nd.rs:5: other\n",
+        );
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].location(), "a.rs:1:2");
+        let related: Vec<String> = parsed[0].related.iter().map(Parsed::location).collect();
+        assert_eq!(related, vec!["b.rs:3", "c.rs:4"]);
+        assert_eq!(parsed[0].related[0].message, "first");
+        assert!(parsed[1].related.is_empty());
+
+        assert_eq!(Parser::default().parse("b.rs:3: note: first").len(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::git;
+use anyhow::{Context, Result};
+use similar::TextDiff;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str,
+};
+
+// lines of unchanged context around each change
+const CONTEXT: usize = 3;
+
+/// Writes the original contents back to the modified files and returns their changes as a patch
+///
+/// The paths in the patch are relative to the root of the git repository, or else the working
+/// directory, so that it applies with `git apply` or `patch -p1` there. Nothing is reverted if
+/// the changes of a file cannot be written as text.
+pub fn revert(originals: &BTreeMap<PathBuf, Vec<u8>>) -> Result<String> {
+    let cwd = fs::canonicalize(env::current_dir()?)?;
+    let base = git::toplevel(&cwd).unwrap_or(cwd);
+    let mut patch = String::new();
+    for (path, original) in originals {
+        let modified =
+            fs::read(path).with_context(|| format!("Cannot read \"{}\"", path.display()))?;
+        let name = relative(&base, path);
+        patch.push_str(&unified(&name, original, &modified)?);
+    }
+    for (path, original) in originals {
+        fs::write(path, original)
+            .with_context(|| format!("Cannot revert \"{}\"", path.display()))?;
+    }
+    Ok(patch)
+}
+
+fn relative(base: &Path, path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let path = path.strip_prefix(base).unwrap_or(&path);
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Returns the unified diff of the contents of a file, or nothing if they are the same
+///
+/// Fails if either contents is not UTF-8, since binary changes cannot be written as a diff.
+pub fn unified(name: &str, before: &[u8], after: &[u8]) -> Result<String> {
+    if before == after {
+        return Ok(String::new());
+    }
+    let text = |contents| {
+        str::from_utf8(contents).with_context(|| {
+            format!(
+                "Cannot write the changes of \"{}\" as a patch, as it is not UTF-8 text",
+                name
+            )
+        })
+    };
+    let diff = TextDiff::from_lines(text(before)?, text(after)?)
+        .unified_diff()
+        .context_radius(CONTEXT)
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string();
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{revert, unified};
+    use std::{collections::BTreeMap, fs};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn hunks() {
+        let unified = |before: &[u8], after: &[u8]| unified("a.rs", before, after).unwrap();
+        assert_eq!(unified(b"a\nb\n", b"a\nb\n"), "");
+        assert_eq!(
+            unified(
+                b"1\n2\n3\n4\n5\n6\n7\n8\n9\n",
+                b"1\n2\n3\n4\nfive\n6\n7\n8\n9\n"
+            ),
+            "--- a/a.rs\n+++ b/a.rs\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+        assert_eq!(
+            unified(b"", b"x"),
+            "--- a/a.rs\n+++ b/a.rs\n@@ -0,0 +1 @@\n+x\n\\ No newline at end of file\n"
+        );
+
+        let before: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let after: String = (1..=20)
+            .filter(|&i| i != 19)
+            .map(|i| match i {
+                2 => "two\n".to_owned(),
+                i => format!("{}\n", i),
+            })
+            .collect();
+        let patch = unified(before.as_bytes(), after.as_bytes());
+        assert_eq!(patch.matches("@@ -").count(), 2, "{}", patch);
+        assert!(
+            patch.contains("@@ -1,5 +1,5 @@\n 1\n-2\n+two\n"),
+            "{}",
+            patch
+        );
+        assert!(
+            patch.contains("@@ -16,5 +16,4 @@\n 16\n 17\n 18\n-19\n 20\n"),
+            "{}",
+            patch
+        );
+    }
+
+    #[test]
+    fn binary() {
+        assert!(unified("a.bin", b"\xff\0", b"\xfe\0").is_err());
+    }
+
+    #[test]
+    fn revert_files() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("a.rs");
+        fs::write(&path, "fn a(){}\n").unwrap();
+        let originals = BTreeMap::from([(path.clone(), b"fn a() {}\n".to_vec())]);
+        let patch = revert(&originals).unwrap();
+        assert!(
+            patch.ends_with("a.rs\n@@ -1 +1 @@\n-fn a() {}\n+fn a(){}\n"),
+            "{}",
+            patch
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"fn a() {}\n");
+
+        // binary files are left modified along with the others
+        let binary = root.path().join("b.bin");
+        fs::write(&path, "fn a(){}\n").unwrap();
+        fs::write(&binary, b"\xfe\0").unwrap();
+        let originals = BTreeMap::from([
+            (path.clone(), b"fn a() {}\n".to_vec()),
+            (binary.clone(), b"\xff\0".to_vec()),
+        ]);
+        assert!(revert(&originals).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"fn a(){}\n");
+    }
+}
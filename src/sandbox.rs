@@ -0,0 +1,153 @@
+use crate::interrupt;
+use anyhow::{Context, Result};
+use log::debug;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tempfile::TempDir;
+
+// directories to remove if the process is interrupted
+static ACTIVE: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Temporary directory holding copies of files, removed when dropped
+pub struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    pub fn new() -> Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("multilint-sandbox-")
+            .tempdir()
+            .context("Cannot create sandbox")?;
+        ACTIVE.lock().unwrap().push(dir.path().to_owned());
+        interrupt::install();
+        Ok(Self { dir })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Copies files under the root to the same relative paths in the sandbox
+    pub fn copy(&self, root: impl AsRef<Path>, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let root = root.as_ref();
+        files
+            .iter()
+            .map(|file| {
+                let relative = file
+                    .strip_prefix(root)
+                    .with_context(|| format!("{} is outside the root", file.display()))?;
+                let copy = self.path().join(relative);
+                if let Some(parent) = copy.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(file, &copy)
+                    .with_context(|| format!("Cannot copy {} to sandbox", file.display()))?;
+                Ok(copy)
+            })
+            .collect()
+    }
+
+    /// Copies the other files in the directories from the root to the files, where linters look
+    /// for their configs such as `rustfmt.toml` or `.eslintrc.json`
+    ///
+    /// Subdirectories are not copied, and files that cannot be read are skipped.
+    pub fn copy_context(&self, root: impl AsRef<Path>, files: &[PathBuf]) -> Result<()> {
+        let root = root.as_ref();
+        let mut dirs = BTreeSet::new();
+        for file in files {
+            for dir in file.ancestors().skip(1) {
+                // the ancestors of a known directory are known too
+                if !dir.starts_with(root) || !dirs.insert(dir) {
+                    break;
+                }
+            }
+        }
+        for dir in dirs {
+            let copy_dir = self.path().join(dir.strip_prefix(root)?);
+            fs::create_dir_all(&copy_dir)?;
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let (path, copy) = (entry.path(), copy_dir.join(entry.file_name()));
+                if !path.is_file() || copy.exists() {
+                    continue;
+                }
+                if let Err(e) = fs::copy(&path, &copy) {
+                    debug!("cannot copy {} to sandbox: {}", path.display(), e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps a path in the sandbox back to the root
+    pub fn original(&self, root: impl AsRef<Path>, copy: &Path) -> PathBuf {
+        match copy.strip_prefix(self.path()) {
+            Ok(relative) => root.as_ref().join(relative),
+            Err(_) => copy.to_owned(),
+        }
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        ACTIVE.lock().unwrap().retain(|dir| dir != self.dir.path());
+    }
+}
+
+/// Removes the sandboxes in use, such as before exiting on Ctrl-C
+pub fn cleanup() {
+    // the lock may be held by the interrupted thread
+    if let Ok(active) = ACTIVE.try_lock() {
+        for dir in active.iter() {
+            debug!("removing sandbox {}", dir.display());
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sandbox;
+    use std::fs::{self, create_dir_all};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    #[test]
+    fn copy() {
+        let root = tempdir().unwrap();
+        let src = root.path().join("src");
+        create_dir_all(&src).unwrap();
+        fs::write(src.join("lib.rs"), "lib").unwrap();
+
+        let sandbox = Sandbox::new().unwrap();
+        let path = sandbox.path().to_owned();
+        let copies = sandbox.copy(&root, &[src.join("lib.rs")]).unwrap();
+        assert_eq!(copies, vec![path.join("src").join("lib.rs")]);
+        assert_eq!(fs::read_to_string(&copies[0]).unwrap(), "lib");
+        assert_eq!(
+            sandbox.original(&root, &copies[0]),
+            root.path().join("src").join("lib.rs")
+        );
+        assert!(sandbox.copy(&root, &[path.join("outside.rs")]).is_err());
+
+        fs::write(root.path().join("rustfmt.toml"), "config").unwrap();
+        fs::write(src.join("main.rs"), "main").unwrap();
+        create_dir_all(root.path().join("other")).unwrap();
+        fs::write(root.path().join("other").join("a.rs"), "other").unwrap();
+        sandbox.copy_context(&root, &[src.join("lib.rs")]).unwrap();
+        assert_eq!(
+            fs::read_to_string(path.join("rustfmt.toml")).unwrap(),
+            "config"
+        );
+        assert!(path.join("src").join("main.rs").exists());
+        assert!(!path.join("other").exists());
+
+        drop(sandbox);
+        assert!(!path.exists());
+    }
+}
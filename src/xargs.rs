@@ -1,10 +1,19 @@
+use crate::interrupt;
 use anyhow::Result;
 use argmax;
 use log::debug;
 use std::{
+    env,
     ffi::{OsStr, OsString},
+    io::{self, Read, Write},
     path::{Path, PathBuf},
-    process,
+    process::{self, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 #[cfg(unix)]
@@ -12,22 +21,125 @@ use std::os::unix::process::ExitStatusExt;
 #[cfg(windows)]
 use std::os::windows::process::ExitStatusExt;
 
+/// Merged output of all batches
+pub struct Output {
+    pub process: process::Output,
+
+    /// Number of times the program was run
+    pub batches: usize,
+
+    /// Whether a run was killed for exceeding the timeout, leaving the remaining batches unrun
+    pub timed_out: bool,
+
+    /// Status of the failing batch telling the most, one killed by a signal or else the one with
+    /// the highest exit code, since the status of several batches is 123
+    pub failure: Option<process::ExitStatus>,
+}
+
+/// Exit code of a run killed for exceeding the timeout, as `timeout(1)` returns
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+// interval between checks whether a child with a timeout has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// width of the pseudo-terminal, wide enough that tools do not wrap their messages
+#[cfg(unix)]
+const PTY_COLUMNS: u16 = 400;
+
+// process groups of the runs with a timeout and their kill signals, to terminate if the process
+// is interrupted
+static GROUPS: Mutex<Vec<(u32, Option<KillSignal>)>> = Mutex::new(Vec::new());
+
+// signal sent to a run exceeding its timeout, and the grace period before it is killed
+type KillSignal = (i32, Duration);
+
+// time to collect the output written before a kill, which descendants may keep open
+const KILL_GRACE: Duration = Duration::from_millis(100);
+
+/// Counting semaphore bounding the number of child processes running at once
+#[derive(Debug)]
+pub struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Permit to run a child process, released when dropped
+pub struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    /// Allows `permits` processes at once, at least one
+    pub fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a permit is available
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut available = self.lock();
+        while *available == 0 {
+            available = self
+                .released
+                .wait(available)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= 1;
+        Permit { semaphore: self }
+    }
+
+    // a panic while holding the lock leaves the count intact
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.available.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.lock() += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
 pub struct Xargs {
     program: OsString,
     max_args: Option<usize>,
     common_args: Vec<OsString>,
     args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
     current_dir: Option<PathBuf>,
+    timeout: Option<Duration>,
+    max_procs: Option<usize>,
+    semaphore: Option<Arc<Semaphore>>,
+    pty: bool,
+    kill_signal: Option<(i32, Duration)>,
+    stream: bool,
+    prefix: Option<String>,
 }
 
 impl Xargs {
-    pub fn new(program: impl AsRef<OsStr>, max_args: Option<usize>) -> Self {
+    /// Runs `program` with at most `max_args` arguments per batch and `max_procs` batches at once
+    pub fn new(
+        program: impl AsRef<OsStr>,
+        max_args: Option<usize>,
+        max_procs: Option<usize>,
+    ) -> Self {
         Self {
             program: program.as_ref().to_os_string(),
             max_args,
+            max_procs,
             common_args: vec![],
             args: vec![],
+            envs: vec![],
             current_dir: None,
+            timeout: None,
+            semaphore: None,
+            pty: false,
+            kill_signal: None,
+            stream: false,
+            prefix: None,
         }
     }
 
@@ -66,15 +178,131 @@ impl Xargs {
         self
     }
 
-    pub fn output(&self) -> Result<process::Output> {
-        let mut ret = process::Output {
-            status: process::ExitStatus::from_raw(0),
-            stdout: Vec::new(),
-            stderr: Vec::new(),
-        };
+    /// Sets an environment variable of the program
+    pub fn env(&mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> &mut Self {
+        self.envs
+            .push((key.as_ref().to_os_string(), value.as_ref().to_os_string()));
+        self
+    }
+
+    /// Kills each run of the program exceeding the duration
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Copies the output of the program to that of this process as it is written, still capturing it
+    pub fn stream(&mut self) -> &mut Self {
+        self.stream = true;
+        self
+    }
+
+    /// Prefixes each line copied by `stream` with `[prefix] `, writing whole lines at once so that
+    /// the output of programs run at the same time is not garbled
+    pub fn prefix_lines(&mut self, prefix: impl Into<String>) -> &mut Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Sends the signal to a run exceeding the timeout, and kills it only after the grace period
+    /// (unix only)
+    pub fn kill_signal(&mut self, signal: i32, grace: Duration) -> &mut Self {
+        self.kill_signal = Some((signal, grace));
+        self
+    }
+
+    /// Runs each batch holding a permit of the semaphore
+    pub fn semaphore(&mut self, semaphore: Arc<Semaphore>) -> &mut Self {
+        self.semaphore = Some(semaphore);
+        self
+    }
+
+    /// Runs the program in a pseudo-terminal, which merges stderr into stdout (unix only)
+    pub fn pty(&mut self) -> &mut Self {
+        self.pty = true;
+        self
+    }
 
+    /// Runs the batches, up to `max_procs` at once, and merges their output in order
+    pub fn output(&self) -> Result<Output> {
         debug!("current_dir: {:?}", self.current_dir);
 
+        let batches = self.batches()?;
+        let count = batches.len();
+        let queue = Mutex::new(batches.into_iter().enumerate());
+        let results = Mutex::new((0..count).map(|_| None).collect::<Vec<_>>());
+        let stop = AtomicBool::new(false);
+        let worker = || loop {
+            let next = queue.lock().unwrap().next();
+            let Some((i, (mut cmd, debug_cmd))) = next else {
+                break;
+            };
+            // a timeout or an error leaves the remaining batches unrun
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+            let result = self.run(&mut cmd, &debug_cmd);
+            if !matches!(result, Ok((_, false))) {
+                stop.store(true, Ordering::SeqCst);
+            }
+            results.lock().unwrap()[i] = Some(result);
+        };
+        match self.max_procs.unwrap_or(1).min(count) {
+            0 | 1 => worker(),
+            procs => thread::scope(|s| {
+                for _ in 0..procs {
+                    s.spawn(worker);
+                }
+            }),
+        }
+
+        let mut ret = Output {
+            process: process::Output {
+                status: process::ExitStatus::from_raw(0),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            },
+            batches: 0,
+            timed_out: false,
+            failure: None,
+        };
+        for result in results.into_inner().unwrap().into_iter().flatten() {
+            let (output, timed_out) = result?;
+            ret.batches += 1;
+            if timed_out {
+                debug!("killed after {:?}", self.timeout);
+                ret.timed_out = true;
+            } else if !output.status.success() {
+                ret.failure = Some(worse(ret.failure, output.status));
+                ret.process.status = if count == 1 {
+                    // a single run exits as if the program was run directly
+                    output.status
+                } else {
+                    // https://man.archlinux.org/man/xargs.1.en#EXIT_STATUS
+                    exit_status(123)
+                };
+            }
+            ret.process.stdout.extend(output.stdout);
+            ret.process.stderr.extend(output.stderr);
+        }
+        if ret.timed_out {
+            ret.process.status = exit_status(TIMEOUT_EXIT_CODE);
+        }
+        Ok(ret)
+    }
+
+    /// Returns the command line of each batch that `output` would run
+    pub fn commands(&self) -> Result<Vec<Vec<OsString>>> {
+        Ok(self
+            .batches()?
+            .into_iter()
+            .map(|(_, argv)| argv.into_iter().cloned().collect())
+            .collect())
+    }
+
+    // splits the arguments into commands within the limits of the system and `max_args`
+    fn batches(&self) -> Result<Vec<(argmax::Command, Vec<&OsString>)>> {
+        let mut batches = vec![];
         let mut args: &[OsString] = &self.args;
         loop {
             let mut cmd = argmax::Command::new(&self.program);
@@ -98,49 +326,698 @@ impl Xargs {
                 }
                 args = &args[i..];
             }
-            debug!("command: {:?}", debug_cmd);
-            let output = cmd.output()?;
-            if !output.status.success() {
-                // https://man.archlinux.org/man/xargs.1.en#EXIT_STATUS
-                ret.status = process::ExitStatus::from_raw(123);
-            }
-            ret.stdout.extend(output.stdout);
-            ret.stderr.extend(output.stderr);
-
+            batches.push((cmd, debug_cmd));
             if args.is_empty() {
                 break;
             }
         }
-        Ok(ret)
+        Ok(batches)
+    }
+
+    // runs a batch holding a permit of the semaphore
+    fn run(
+        &self,
+        cmd: &mut argmax::Command,
+        debug_cmd: &[&OsString],
+    ) -> Result<(process::Output, bool)> {
+        debug!("command: {:?}", debug_cmd);
+        let _permit = self.semaphore.as_deref().map(Semaphore::acquire);
+        Ok(match self.timeout {
+            _ if self.pty => output_in_pty(
+                debug_cmd,
+                self.current_dir.as_deref(),
+                &self.envs,
+                self.timeout,
+                self.echo(false),
+            )?,
+            // argmax::Command cannot set the environment, unlike the std command of output_piped
+            None if !self.stream && self.envs.is_empty() => (cmd.output()?, false),
+            timeout => output_piped(
+                debug_cmd,
+                self.current_dir.as_deref(),
+                &self.envs,
+                timeout,
+                self.kill_signal,
+                [self.echo(false), self.echo(true)],
+            )?,
+        })
+    }
+
+    fn echo(&self, stderr: bool) -> Option<Echo> {
+        self.stream.then(|| Echo {
+            out: match stderr {
+                true => Box::new(io::stderr()),
+                false => Box::new(io::stdout()),
+            },
+            prefix: self.prefix.as_ref().map(|prefix| format!("[{}] ", prefix)),
+            partial: vec![],
+        })
     }
 }
 
+// a signal, such as a segmentation fault, outranks any exit code
+fn worse(a: Option<process::ExitStatus>, b: process::ExitStatus) -> process::ExitStatus {
+    match (a, b.code()) {
+        (Some(a), Some(code)) if a.code().is_none_or(|c| c >= code) => a,
+        _ => b,
+    }
+}
+
+/// Runs the command, killing it after the timeout if any with the output collected until then
+///
+/// On unix the command runs in its own process group, so that killing it also kills its
+/// descendants. With `echo`, the output is also copied to that of this process as it is read.
+fn output_piped(
+    argv: &[&OsString],
+    current_dir: Option<&Path>,
+    envs: &[(OsString, OsString)],
+    timeout: Option<Duration>,
+    kill_signal: Option<(i32, Duration)>,
+    [echo_stdout, echo_stderr]: [Option<Echo>; 2],
+) -> io::Result<(process::Output, bool)> {
+    let mut cmd = process::Command::new(argv[0]);
+    cmd.args(&argv[1..]);
+    if let Some(dir) = current_dir {
+        cmd.current_dir(dir);
+    }
+    cmd.envs(envs.iter().map(|(k, v)| (k, v)));
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    GROUPS.lock().unwrap().push((child.id(), kill_signal));
+    interrupt::install();
+    let stdout = Arc::new(Mutex::new(Vec::new()));
+    let stderr = Arc::new(Mutex::new(Vec::new()));
+    let readers = [
+        collect(child.stdout.take(), &stdout, echo_stdout),
+        collect(child.stderr.take(), &stderr, echo_stderr),
+    ];
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            terminate(&mut child, kill_signal)?;
+            timed_out = true;
+            break child.wait()?;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    GROUPS.lock().unwrap().retain(|&(id, _)| id != child.id());
+
+    let grace = Instant::now() + KILL_GRACE;
+    for reader in readers {
+        if timed_out {
+            while !reader.is_finished() && Instant::now() < grace {
+                thread::sleep(POLL_INTERVAL);
+            }
+            // descendants of the killed child may hold the pipe open
+            if !reader.is_finished() {
+                continue;
+            }
+        }
+        reader.join().expect("output reader panicked")?;
+    }
+    let take = |buf: &Arc<Mutex<Vec<u8>>>| std::mem::take(&mut *buf.lock().unwrap());
+    let output = process::Output {
+        status,
+        stdout: take(&stdout),
+        stderr: take(&stderr),
+    };
+    Ok((output, timed_out))
+}
+
+/// Sends the signal to the process group of the child, then kills it after the grace period
+#[cfg(unix)]
+fn terminate(child: &mut process::Child, kill_signal: Option<(i32, Duration)>) -> io::Result<()> {
+    let group = -(child.id() as libc::pid_t);
+    if let Some((signal, grace)) = kill_signal {
+        debug!("sending signal {} to {}", signal, child.id());
+        // SAFETY: kill only sends a signal
+        unsafe { libc::kill(group, signal) };
+        let deadline = Instant::now() + grace;
+        while child.try_wait()?.is_none() && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    // also kills the descendants left in the group after the child exits
+    // SAFETY: kill only sends a signal
+    unsafe { libc::kill(group, libc::SIGKILL) };
+    Ok(())
+}
+
+/// Kills the child with `TerminateProcess`, as there are no signals
+#[cfg(not(unix))]
+fn terminate(child: &mut process::Child, _kill_signal: Option<(i32, Duration)>) -> io::Result<()> {
+    child.kill()
+}
+
+/// Terminates the process groups of the runs with a timeout, such as before exiting on Ctrl-C
+///
+/// Unlike other runs, they are not in the foreground process group receiving the interrupt. Each
+/// group gets its kill signal, or `SIGTERM`, and is killed once its grace period is over.
+#[cfg(unix)]
+pub fn terminate_all() {
+    // runs only hold the lock while starting or finishing, and a panic leaves the list intact
+    let groups = GROUPS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let mut deadline = Instant::now();
+    for &(id, kill_signal) in &groups {
+        let (signal, grace) = kill_signal.unwrap_or((libc::SIGTERM, KILL_GRACE));
+        debug!("sending signal {} to {}", signal, id);
+        // SAFETY: kill only sends a signal
+        unsafe { libc::kill(-(id as libc::pid_t), signal) };
+        deadline = deadline.max(Instant::now() + grace);
+    }
+    // SAFETY: a signal of 0 only checks whether the group still exists
+    let alive = |&(id, _): &(u32, _)| unsafe { libc::kill(-(id as libc::pid_t), 0) == 0 };
+    while groups.iter().any(alive) && Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL);
+    }
+    for &(id, _) in groups.iter().filter(|group| alive(group)) {
+        debug!("killing {}", id);
+        // SAFETY: kill only sends a signal
+        unsafe { libc::kill(-(id as libc::pid_t), libc::SIGKILL) };
+    }
+}
+
+/// Does nothing, as the runs are not in a process group of their own
+#[cfg(not(unix))]
+pub fn terminate_all() {}
+
+/// Parses a signal name such as `TERM` or `SIGTERM`, or a number
+#[cfg(unix)]
+pub fn parse_signal(name: &str) -> Result<i32> {
+    let upper = name.to_ascii_uppercase();
+    Ok(match upper.strip_prefix("SIG").unwrap_or(&upper) {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "TERM" => libc::SIGTERM,
+        number => number
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| anyhow::anyhow!("Unknown signal \"{}\"", name))?,
+    })
+}
+
+/// Accepts any signal, since runs are killed with `TerminateProcess`
+#[cfg(not(unix))]
+pub fn parse_signal(_name: &str) -> Result<i32> {
+    Ok(0)
+}
+
+/// Runs the command in a pseudo-terminal, killing it after the timeout if any
+///
+/// Tools see a terminal, so they color their output as if run interactively. The terminal merges
+/// stderr into stdout and ends lines with `\r\n`, which are converted back to `\n`.
+#[cfg(unix)]
+fn output_in_pty(
+    argv: &[&OsString],
+    current_dir: Option<&Path>,
+    envs: &[(OsString, OsString)],
+    timeout: Option<Duration>,
+    echo: Option<Echo>,
+) -> Result<(process::Output, bool)> {
+    use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+    let pair = native_pty_system().openpty(PtySize {
+        rows: 24,
+        cols: PTY_COLUMNS,
+        pixel_width: 0,
+        pixel_height: 0,
+    })?;
+    let mut cmd = CommandBuilder::from_argv(argv.iter().map(|&arg| arg.clone()).collect());
+    // the command would start in the home directory otherwise
+    cmd.cwd(match current_dir {
+        Some(dir) => dir.to_owned(),
+        None => env::current_dir()?,
+    });
+    for (key, value) in envs {
+        cmd.env(key, value);
+    }
+    let mut child = pair.slave.spawn_command(cmd)?;
+    // reading ends when the child closes the last handle of the terminal
+    drop(pair.slave);
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let reader = collect(Some(pair.master.try_clone_reader()?), &buf, echo);
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            child.kill()?;
+            timed_out = true;
+            break child.wait()?;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let grace = Instant::now() + KILL_GRACE;
+    while timed_out && !reader.is_finished() && Instant::now() < grace {
+        thread::sleep(POLL_INTERVAL);
+    }
+    if !timed_out || reader.is_finished() {
+        reader.join().expect("output reader panicked")?;
+    }
+    let output = std::mem::take(&mut *buf.lock().unwrap());
+    let mut stdout = Vec::with_capacity(output.len());
+    for (i, &b) in output.iter().enumerate() {
+        if !(b == b'\r' && output.get(i + 1) == Some(&b'\n')) {
+            stdout.push(b);
+        }
+    }
+    let output = process::Output {
+        status: exit_status(status.exit_code() as i32),
+        stdout,
+        stderr: vec![],
+    };
+    Ok((output, timed_out))
+}
+
+#[cfg(not(unix))]
+fn output_in_pty(
+    _argv: &[&OsString],
+    _current_dir: Option<&Path>,
+    _envs: &[(OsString, OsString)],
+    _timeout: Option<Duration>,
+    _echo: Option<Echo>,
+) -> Result<(process::Output, bool)> {
+    anyhow::bail!("pty is only supported on unix")
+}
+
+// serializes the output copied from children running at once
+static OUTPUT: Mutex<()> = Mutex::new(());
+
+// copies the output of a child as it is read, in whole lines if they are prefixed
+struct Echo {
+    out: Box<dyn Write + Send>,
+    prefix: Option<String>,
+    partial: Vec<u8>,
+}
+
+impl Echo {
+    // returns the output to write for a chunk, holding back a prefixed line until it ends
+    fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let prefix = match &self.prefix {
+            Some(prefix) => prefix.as_bytes(),
+            None => return chunk.to_vec(),
+        };
+        self.partial.extend_from_slice(chunk);
+        let end = match self.partial.iter().rposition(|&b| b == b'\n') {
+            Some(i) => i + 1,
+            None => return vec![],
+        };
+        let mut lines = Vec::with_capacity(end + prefix.len());
+        for line in self
+            .partial
+            .drain(..end)
+            .as_slice()
+            .split_inclusive(|&b| b == b'\n')
+        {
+            lines.extend_from_slice(prefix);
+            lines.extend_from_slice(line);
+        }
+        lines
+    }
+
+    // returns the last line if the output does not end with a newline
+    fn finish(&mut self) -> Vec<u8> {
+        match self.partial.is_empty() {
+            true => vec![],
+            false => self.push(b"\n"),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let _guard = OUTPUT.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = self.out.write_all(bytes).and_then(|_| self.out.flush());
+    }
+}
+
+// reads the pipe into the buffer on a thread, so that the output before a kill is kept
+fn collect(
+    pipe: Option<impl Read + Send + 'static>,
+    buf: &Arc<Mutex<Vec<u8>>>,
+    mut echo: Option<Echo>,
+) -> thread::JoinHandle<io::Result<()>> {
+    let buf = Arc::clone(buf);
+    thread::spawn(move || {
+        let mut pipe = match pipe {
+            Some(pipe) => pipe,
+            None => return Ok(()),
+        };
+        let mut chunk = [0; 8192];
+        loop {
+            match pipe.read(&mut chunk) {
+                Ok(0) => {
+                    if let Some(echo) = &mut echo {
+                        let last = echo.finish();
+                        echo.write(&last);
+                    }
+                    return Ok(());
+                }
+                Ok(n) => {
+                    if let Some(echo) = &mut echo {
+                        let lines = echo.push(&chunk[..n]);
+                        echo.write(&lines);
+                    }
+                    buf.lock().unwrap().extend_from_slice(&chunk[..n]);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    })
+}
+
+#[cfg(unix)]
+pub(crate) fn exit_status(code: i32) -> process::ExitStatus {
+    process::ExitStatus::from_raw(code << 8)
+}
+
+/// Status of a process killed by the signal
+#[cfg(unix)]
+pub(crate) fn signal_status(signal: i32) -> process::ExitStatus {
+    process::ExitStatus::from_raw(signal)
+}
+
+#[cfg(windows)]
+pub(crate) fn exit_status(code: i32) -> process::ExitStatus {
+    process::ExitStatus::from_raw(code as u32)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Xargs;
+    #[cfg(unix)]
+    use super::parse_signal;
+    use super::{Echo, Semaphore, Xargs};
+    #[cfg(unix)]
+    use std::os::unix::process::ExitStatusExt;
+    use std::{
+        fs,
+        io::{self, Write},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+    use tempfile::tempdir;
     use test_log::test;
 
     #[test]
     fn no_arg() {
-        let output = Xargs::new("echo", Some(2))
+        let output = Xargs::new("echo", Some(2), None)
             .common_arg("c")
             .output()
             .unwrap();
-        assert!(output.status.success());
-        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.process.status.success());
+        assert_eq!(output.batches, 1);
+        let stdout = std::str::from_utf8(&output.process.stdout).unwrap();
         assert!(stdout.contains("c"));
     }
 
     #[test]
     fn max_args() {
-        let output = Xargs::new("echo", Some(2))
+        let output = Xargs::new("echo", Some(2), None)
             .common_arg("c")
             .args(["1", "2", "3"])
             .output()
             .unwrap();
-        assert!(output.status.success());
-        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.process.status.success());
+        assert_eq!(output.batches, 2);
+        let stdout = std::str::from_utf8(&output.process.stdout).unwrap();
         assert!(stdout.contains("c 1 2"));
         assert!(stdout.contains("c 3"));
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_code() {
+        let output = Xargs::new("sh", None, None)
+            .common_args(["-c", "exit 3"])
+            .output()
+            .unwrap();
+        assert_eq!(output.process.status.code(), Some(3));
+
+        let output = Xargs::new("sh", Some(1), None)
+            .common_args(["-c", "exit $0"])
+            .args(["0", "3", "2"])
+            .output()
+            .unwrap();
+        assert_eq!(output.process.status.code(), Some(123));
+        assert_eq!(output.failure.unwrap().code(), Some(3));
+
+        let output = Xargs::new("sh", Some(1), None)
+            .common_args(["-c", "test $0 = kill && kill -SEGV $$; exit 1"])
+            .args(["1", "kill", "1"])
+            .output()
+            .unwrap();
+        assert_eq!(output.process.status.code(), Some(123));
+        assert_eq!(output.failure.unwrap().signal(), Some(11));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn max_procs() {
+        // each batch counts the batches running alongside it
+        let dir = tempdir().unwrap();
+        let running = dir.path().join("running");
+        let counts = dir.path().join("counts");
+        fs::create_dir(&running).unwrap();
+        let script = format!(
+            "touch {running}/$0; sleep 0.$((5 - $0)); ls {running} | wc -l >> {counts}; rm {running}/$0; echo $0; exit $(($0 == 2))",
+            running = running.display(),
+            counts = counts.display()
+        );
+        let output = Xargs::new("sh", Some(1), Some(2))
+            .common_args(["-c", &script])
+            .args(["1", "2", "3", "4"])
+            .output()
+            .unwrap();
+        assert_eq!(output.batches, 4);
+        assert_eq!(output.process.stdout, b"1\n2\n3\n4\n");
+        assert_eq!(output.process.status.code(), Some(123));
+        let counts = fs::read_to_string(&counts).unwrap();
+        let peak = counts.lines().map(|line| line.trim().parse().unwrap());
+        assert_eq!(peak.max(), Some(2), "{}", counts);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout() {
+        let output = Xargs::new("sh", Some(1), None)
+            .common_args(["-c", "echo $0; echo err >&2; exec sleep $0"])
+            .args(["10", "0"])
+            .timeout(Duration::from_millis(200))
+            .output()
+            .unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.batches, 1);
+        assert_eq!(output.process.status.code(), Some(124));
+        assert_eq!(output.process.stdout, b"10\n");
+        assert_eq!(output.process.stderr, b"err\n");
+
+        let output = Xargs::new("echo", None, None)
+            .arg("a")
+            .timeout(Duration::from_secs(10))
+            .output()
+            .unwrap();
+        assert!(!output.timed_out);
+        assert!(output.process.status.success());
+        assert_eq!(output.process.stdout, b"a\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn env() {
+        let script = "echo $A $B";
+        let run = |xargs: &mut Xargs| xargs.output().unwrap().process.stdout;
+        let mut xargs = Xargs::new("sh", None, None);
+        xargs
+            .common_args(["-c", script])
+            .env("A", "a")
+            .env("B", "b");
+        assert_eq!(run(&mut xargs), b"a b\n");
+        assert_eq!(run(xargs.stream()), b"a b\n");
+        assert_eq!(run(xargs.pty()), b"a b\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stream() {
+        let output = Xargs::new("sh", None, None)
+            .common_args(["-c", "echo out; echo err >&2; exit 2"])
+            .stream()
+            .output()
+            .unwrap();
+        assert_eq!(output.process.status.code(), Some(2));
+        assert_eq!(output.process.stdout, b"out\n");
+        assert_eq!(output.process.stderr, b"err\n");
+    }
+
+    // writes one byte at a time, letting other threads in between
+    struct Trickle(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().push(buf[0]);
+            thread::yield_now();
+            Ok(1)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn prefix_lines() {
+        // read byte by byte from several threads, each written line stays whole
+        let written = Arc::new(Mutex::new(Vec::new()));
+        thread::scope(|s| {
+            for id in 0..4 {
+                let written = Arc::clone(&written);
+                s.spawn(move || {
+                    let mut echo = Echo {
+                        out: Box::new(Trickle(written)),
+                        prefix: Some(format!("[{}] ", id)),
+                        partial: vec![],
+                    };
+                    let output = format!("{0} one\n{0} two\n{0} three", id);
+                    for b in output.bytes() {
+                        let lines = echo.push(&[b]);
+                        echo.write(&lines);
+                    }
+                    let last = echo.finish();
+                    echo.write(&last);
+                });
+            }
+        });
+        let written = String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        let mut lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 12, "{}", written);
+        for line in &lines {
+            let id = &line[1..2];
+            assert!(line.starts_with(&format!("[{0}] {0} ", id)), "{}", line);
+        }
+        lines.retain(|line| line.starts_with("[2]"));
+        assert_eq!(lines, vec!["[2] 2 one", "[2] 2 two", "[2] 2 three"]);
+
+        let mut echo = Echo {
+            out: Box::new(io::sink()),
+            prefix: None,
+            partial: vec![],
+        };
+        assert_eq!(echo.push(b"a"), b"a");
+        assert!(echo.finish().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kill_signal() {
+        assert_eq!(parse_signal("TERM").unwrap(), libc::SIGTERM);
+        assert_eq!(parse_signal("sigint").unwrap(), libc::SIGINT);
+        assert_eq!(parse_signal("9").unwrap(), libc::SIGKILL);
+        assert!(parse_signal("BOGUS").is_err());
+
+        // the descendant ignores the signal and keeps appending to the file until it is killed
+        let dir = tempdir().unwrap();
+        let ticks = dir.path().join("ticks");
+        let script = "trap 'echo cleanup; exit 3' TERM; \
+            (trap '' TERM; while :; do echo >> \"$0\"; sleep 0.02; done) > /dev/null & wait";
+        let output = Xargs::new("sh", None, None)
+            .common_args(["-c", script])
+            .arg(&ticks)
+            .timeout(Duration::from_millis(200))
+            .kill_signal(libc::SIGTERM, Duration::from_secs(5))
+            .output()
+            .unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.process.status.code(), Some(124));
+        assert_eq!(output.process.stdout, b"cleanup\n");
+
+        // the descendants are killed with the group
+        let len = || fs::metadata(&ticks).unwrap().len();
+        thread::sleep(Duration::from_millis(100));
+        let killed = len();
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(len(), killed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pty() {
+        let script = "test -t 1 && echo tty; echo err >&2; exit 3";
+        let output = Xargs::new("sh", None, None)
+            .common_args(["-c", script])
+            .output()
+            .unwrap();
+        assert_eq!(output.process.stdout, b"");
+
+        let output = Xargs::new("sh", None, None)
+            .common_args(["-c", script])
+            .pty()
+            .output()
+            .unwrap();
+        assert_eq!(output.process.status.code(), Some(3));
+        assert_eq!(output.process.stdout, b"tty\nerr\n");
+        assert!(output.process.stderr.is_empty());
+
+        let output = Xargs::new("sleep", None, None)
+            .arg("10")
+            .pty()
+            .timeout(Duration::from_millis(200))
+            .output()
+            .unwrap();
+        assert!(output.timed_out);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn semaphore() {
+        let semaphore = Semaphore::new(2);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..6 {
+                s.spawn(|| {
+                    let _permit = semaphore.acquire();
+                    let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(n, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+        let output = Xargs::new("echo", Some(1), None)
+            .args(["1", "2"])
+            .semaphore(Arc::new(Semaphore::new(1)))
+            .output()
+            .unwrap();
+        assert_eq!(output.batches, 2);
+    }
 }